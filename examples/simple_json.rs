@@ -3,6 +3,7 @@ use std::{fs::File, time::Instant};
 use serde::{Deserialize, Serialize};
 use string_combiner::{
     segment::{Segment, SegmentKind},
+    token::Utf8Byte,
     StringCombiner,
 };
 
@@ -20,16 +21,16 @@ fn main() {
 
     let inputs = segments.into_iter().map(|Segment { key, value }| Segment {
         key,
-        value: value.text.into_bytes(),
+        value: Utf8Byte::split(&value.text).collect::<Vec<_>>(),
     });
 
     let combiner = StringCombiner::default();
 
     let instant = Instant::now();
     let combined = combiner
-        .concat_segments(inputs)
+        .concat_segments_raw(inputs)
         .expect("Failed to concat segments");
-    let combined = String::from_utf8_lossy(&combined);
+    let combined = combined.value.to_string();
 
     println!("Output: {combined}");
     println!("Elapsed: {:?}", instant.elapsed());
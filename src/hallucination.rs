@@ -0,0 +1,362 @@
+use std::{cmp::Reverse, ops, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    msa::{AlignedSequence, AlignedToken},
+    segment::{Segment, SegmentKey, SegmentValue},
+    time::TimeKey,
+    token::{span_of, TokenData, TokenSpan},
+    vocab::Vocabulary,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoopSuppression {
+    // NOTE: the number of genuine repetitions to keep in a row
+    pub max_repeats: usize,
+    pub max_ngram: usize,
+    pub min_ngram: usize,
+}
+
+impl Default for LoopSuppression {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            max_repeats: 2,
+            max_ngram: 32,
+            min_ngram: 2,
+        }
+    }
+}
+
+impl LoopSuppression {
+    #[inline]
+    pub fn suppress<T>(&self, seq: &mut AlignedSequence<T>) -> Vec<SuppressedLoop>
+    where
+        T: Clone + PartialEq + TokenSpan,
+    {
+        self.suppress_by(seq, LoopEvidence::from_timing)
+    }
+
+    // NOTE: the copies are removed only with the evidence, and otherwise just reported
+    pub fn suppress_by<T, F>(
+        &self,
+        seq: &mut AlignedSequence<T>,
+        evidence: F,
+    ) -> Vec<SuppressedLoop>
+    where
+        T: Clone + PartialEq,
+        F: Fn(&[AlignedToken<T>], &[AlignedToken<T>]) -> Option<LoopEvidence>,
+    {
+        let Self {
+            max_repeats,
+            max_ngram,
+            min_ngram,
+        } = *self;
+
+        let mut events = Vec::new();
+        let mut offset = 0;
+        while offset < seq.value.len() {
+            let Some((len, num_copies)) = find_loop(&seq.value[offset..], min_ngram..=max_ngram)
+            else {
+                offset += 1;
+                continue;
+            };
+
+            // Tell apart genuine repetitions from the merge artifacts
+            let copy = |index: usize| {
+                let start = offset + index * len;
+                &seq.value[start..start + len]
+            };
+            let mut kept = vec![copy(0).to_vec()];
+            let mut num_artifacts = 0;
+            let mut num_hallucinations = 0;
+            let mut num_unverified = 0;
+            for index in 1..num_copies {
+                let copy = copy(index);
+                match kept.last().and_then(|last| evidence(last, copy)) {
+                    Some(LoopEvidence::Artifact) => num_artifacts += 1,
+                    _ if kept.len() < max_repeats.max(1) => {
+                        kept.push(copy.to_vec());
+                        continue;
+                    }
+                    Some(LoopEvidence::Hallucination) => num_hallucinations += 1,
+                    None => {
+                        num_unverified += 1;
+                        kept.push(copy.to_vec());
+                        continue;
+                    }
+                }
+
+                // Fold the votes of the removed copy into the last kept one
+                if let Some(last) = kept.last_mut() {
+                    for (kept, removed) in last.iter_mut().zip(copy) {
+                        kept.count = kept.count.max(removed.count);
+                    }
+                }
+            }
+
+            let num_kept = kept.len();
+            if num_kept < num_copies || num_unverified > 0 {
+                events.push(SuppressedLoop {
+                    offset,
+                    len,
+                    num_artifacts,
+                    num_hallucinations,
+                    num_kept,
+                    num_unverified,
+                });
+                seq.value.splice(
+                    offset..offset + num_copies * len,
+                    kept.into_iter().flatten(),
+                );
+            }
+            offset += num_kept * len;
+        }
+        events
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LoopEvidence {
+    // NOTE: the same audio has been transcribed twice
+    Artifact,
+    // NOTE: the copy takes no time of its own
+    Hallucination,
+}
+
+impl LoopEvidence {
+    pub fn from_timing<T>(last: &[AlignedToken<T>], copy: &[AlignedToken<T>]) -> Option<Self>
+    where
+        T: TokenSpan,
+    {
+        let (_, last_t1) = span_of(last)?;
+        let (t0, t1) = span_of(copy)?;
+        if t0 == t1 {
+            Some(Self::Hallucination)
+        } else if t0 < last_t1 {
+            Some(Self::Artifact)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SuppressedLoop {
+    // NOTE: the position of the first copy in the suppressed sequence
+    pub offset: usize,
+    pub len: usize,
+    pub num_artifacts: usize,
+    pub num_hallucinations: usize,
+    pub num_kept: usize,
+    // NOTE: the copies over `max_repeats` which are kept for the lack of evidence
+    pub num_unverified: usize,
+}
+
+fn find_loop<T>(
+    tokens: &[AlignedToken<T>],
+    lens: ops::RangeInclusive<usize>,
+) -> Option<(usize, usize)>
+where
+    T: PartialEq,
+{
+    // Prefer the loop covering the most tokens, and then the shortest n-gram
+    lens.filter(|&len| len > 0 && 2 * len <= tokens.len())
+        .map(|len| {
+            let ngram = &tokens[..len];
+            let num_copies = tokens
+                .chunks_exact(len)
+                .take_while(|&chunk| chunk == ngram)
+                .count();
+            (len, num_copies)
+        })
+        .filter(|&(_, num_copies)| num_copies >= 2)
+        .max_by_key(|&(len, num_copies)| (len * num_copies, Reverse(len)))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PhraseFilter {
+    pub edge_margin: Duration,
+    pub min_silence: Duration,
+    pub phrases: Vec<Phrase>,
+}
+
+impl Default for PhraseFilter {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            edge_margin: Duration::from_millis(200),
+            min_silence: Duration::from_millis(500),
+            phrases: Vec::new(),
+        }
+    }
+}
+
+impl PhraseFilter {
+    // NOTE: the text is located by decoding the tokens before the phrase; without a vocabulary,
+    //       the n-th token match is taken for the n-th text match only if they are as many
+    pub fn filter<K>(
+        &self,
+        index: usize,
+        segment: &mut Segment<SegmentKey<K>, SegmentValue>,
+        vocab: Option<&Vocabulary>,
+        unmatched: &mut Vec<UnmatchedPhrase>,
+    ) -> Vec<RemovedPhrase>
+    where
+        K: TimeKey,
+    {
+        let Segment {
+            key,
+            value: SegmentValue { text, tokens, .. },
+        } = segment;
+
+        let mut events = Vec::new();
+        for phrase in &self.phrases {
+            if phrase.tokens.is_empty() {
+                continue;
+            }
+
+            let mut offset = 0;
+            let mut occurrence = 0;
+            while let Some(start) = find_ids(&tokens[offset..], &phrase.tokens).map(|i| offset + i)
+            {
+                let end = start + phrase.tokens.len();
+                let Some(reason) = self.classify(key, tokens, start..end) else {
+                    offset = end;
+                    occurrence += 1;
+                    continue;
+                };
+
+                // NOTE: the tokens are kept unless the text can be removed along with them
+                let (t0, t1) = (tokens[start].t0, tokens[end - 1].t1);
+                let position = match vocab {
+                    Some(vocab) => {
+                        let position = vocab.decode_text(&tokens[..start]).len();
+                        text.get(position..)
+                            .filter(|rest| rest.starts_with(&phrase.text))
+                            .map(|_| position)
+                    }
+                    None if count_ids(tokens, &phrase.tokens)
+                        == text.matches(&phrase.text).count() =>
+                    {
+                        text.match_indices(&phrase.text)
+                            .nth(occurrence)
+                            .map(|(position, _)| position)
+                    }
+                    None => None,
+                };
+                let Some(position) = position else {
+                    unmatched.push(UnmatchedPhrase {
+                        index,
+                        t0,
+                        t1,
+                        text: phrase.text.clone(),
+                    });
+                    offset = end;
+                    occurrence += 1;
+                    continue;
+                };
+                tokens.drain(start..end);
+                text.replace_range(position..position + phrase.text.len(), "");
+                events.push(RemovedPhrase {
+                    index,
+                    reason,
+                    t0,
+                    t1,
+                    text: phrase.text.clone(),
+                });
+                offset = start;
+            }
+        }
+        events
+    }
+
+    fn classify<K>(
+        &self,
+        key: &SegmentKey<K>,
+        tokens: &[TokenData],
+        range: ops::Range<usize>,
+    ) -> Option<RemovalReason>
+    where
+        K: TimeKey,
+    {
+        let t0 = tokens[range.start].t0;
+        let t1 = tokens[range.end - 1].t1;
+
+        // NOTE: if the keys have no timestamps, e.g. `SystemTime`, the tokens span the segment
+        let elapsed =
+            |a: Option<K>, b: Option<K>| a.zip(b).map(|(a, b)| a.elapsed(&b)).unwrap_or_default();
+        let head = elapsed(Some(key.t0), K::from_timestamp(t0));
+        let tail = elapsed(K::from_timestamp(t1), Some(key.t1));
+
+        // Check whether the phrase is the first or the last words of the segment
+        if (range.start == 0 && head <= self.edge_margin)
+            || (range.end == tokens.len() && tail <= self.edge_margin)
+        {
+            return Some(RemovalReason::SegmentEdge);
+        }
+
+        // Check whether the phrase is surrounded by silence
+        let gap_before = match range.start.checked_sub(1) {
+            Some(prev) => t0.saturating_sub(tokens[prev].t1),
+            None => head,
+        };
+        let gap_after = match tokens.get(range.end) {
+            Some(next) => next.t0.saturating_sub(t1),
+            None => tail,
+        };
+        if gap_before >= self.min_silence && gap_after >= self.min_silence {
+            Some(RemovalReason::SilenceGap)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Phrase {
+    pub text: String,
+    pub tokens: Vec<i32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RemovedPhrase {
+    // NOTE: the position of the input segment
+    pub index: usize,
+    pub reason: RemovalReason,
+    pub t0: Duration,
+    pub t1: Duration,
+    pub text: String,
+}
+
+// NOTE: the tokens matched, but the text of the segment does not contain the phrase there
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct UnmatchedPhrase {
+    pub index: usize,
+    pub t0: Duration,
+    pub t1: Duration,
+    pub text: String,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RemovalReason {
+    SegmentEdge,
+    SilenceGap,
+}
+
+fn count_ids(tokens: &[TokenData], ids: &[i32]) -> usize {
+    let mut count = 0;
+    let mut offset = 0;
+    while let Some(start) = find_ids(&tokens[offset..], ids) {
+        count += 1;
+        offset += start + ids.len();
+    }
+    count
+}
+
+fn find_ids(tokens: &[TokenData], ids: &[i32]) -> Option<usize> {
+    tokens
+        .windows(ids.len())
+        .position(|window| window.iter().map(|token| token.id).eq(ids.iter().copied()))
+}
@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+
+const SYLLABLE_BASE: u32 = 0xAC00;
+const SYLLABLE_END: u32 = 0xD7A3;
+const NUM_JUNGSEONG: u32 = 21;
+const NUM_JONGSEONG: u32 = 28;
+
+// NOTE: jamo sharing a class sound alike (e.g. plain, tense and aspirated consonants)
+const CHOSEONG_CLASS: [u8; 19] = [0, 0, 1, 2, 2, 3, 4, 5, 5, 6, 6, 7, 8, 8, 8, 0, 2, 5, 7];
+const JUNGSEONG_CLASS: [u8; 21] = [
+    0, 1, 0, 1, 2, 1, 2, 1, 3, 4, 5, 5, 3, 6, 4, 5, 7, 6, 8, 8, 8,
+];
+const JONGSEONG_CLASS: [u8; 28] = [
+    0, 1, 1, 1, 2, 2, 2, 3, 4, 1, 5, 4, 4, 4, 6, 4, 5, 6, 6, 3, 3, 7, 3, 3, 1, 3, 6, 3,
+];
+
+const WEIGHT_CHOSEONG: f32 = 0.4;
+const WEIGHT_JUNGSEONG: f32 = 0.4;
+const WEIGHT_JONGSEONG: f32 = 0.2;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Jamo {
+    pub choseong: u8,
+    pub jungseong: u8,
+    // NOTE: `0` means no final consonant
+    pub jongseong: u8,
+}
+
+impl Jamo {
+    pub fn decompose(c: char) -> Option<Self> {
+        let index = (c as u32).wrapping_sub(SYLLABLE_BASE);
+        if index > SYLLABLE_END - SYLLABLE_BASE {
+            return None;
+        }
+        Some(Self {
+            choseong: (index / (NUM_JUNGSEONG * NUM_JONGSEONG)) as u8,
+            jungseong: (index / NUM_JONGSEONG % NUM_JUNGSEONG) as u8,
+            jongseong: (index % NUM_JONGSEONG) as u8,
+        })
+    }
+
+    pub fn similarity(&self, other: &Self) -> f32 {
+        fn component(classes: &[u8], a: u8, b: u8) -> f32 {
+            if a == b {
+                1.0
+            } else if classes[a as usize] == classes[b as usize] {
+                0.5
+            } else {
+                0.0
+            }
+        }
+
+        WEIGHT_CHOSEONG * component(&CHOSEONG_CLASS, self.choseong, other.choseong)
+            + WEIGHT_JUNGSEONG * component(&JUNGSEONG_CLASS, self.jungseong, other.jungseong)
+            + WEIGHT_JONGSEONG * component(&JONGSEONG_CLASS, self.jongseong, other.jongseong)
+    }
+}
+
+pub fn similarity(a: char, b: char) -> Option<f32> {
+    Some(Jamo::decompose(a)?.similarity(&Jamo::decompose(b)?))
+}
@@ -1,90 +1,228 @@
+pub mod hallucination;
+pub mod hangul;
+pub mod limit;
 pub mod msa;
+pub mod normalize;
+pub mod report;
 pub mod segment;
+pub mod sequence;
+pub mod session;
+pub mod time;
 pub mod token;
+pub mod vocab;
 
-use std::fmt;
+use std::{
+    fmt,
+    marker::PhantomData,
+    sync::{atomic::AtomicUsize, Arc},
+    time::Duration,
+};
+
+use unicode_segmentation::UnicodeSegmentation;
 
 use crate::{
+    hallucination::{LoopEvidence, LoopSuppression, PhraseFilter},
+    limit::{LimitError, LimitEvent, LimitKind, LimitPolicy, Limits},
     msa::{
-        AlignedSequence, AlignedToken, GreedyMultipleSequenceAligner,
-        GreedyMultipleSequenceAlignerItem, MultipleSequenceAlignment, Scoring, SequenceMatch,
+        AlignedSequence, AlignedToken, AlignerPool, AlignmentVisitor, Budget,
+        GreedyMultipleSequenceAligner, GreedyMultipleSequenceAlignerItem, Parallelism, Scoring,
+        SequenceMatch,
+    },
+    normalize::Normalization,
+    report::{CombineError, CombineReport, Combined},
+    segment::{
+        GapPolicy, Segment, SegmentKey, SegmentKind, SegmentOverlap, SegmentSplit, SegmentValue,
+        Segments, TimedSegment,
+    },
+    sequence::order_segments,
+    time::TimeKey,
+    token::{
+        span_of, AlignmentTokenMergeVisitor, Anchored, Grapheme, HierarchicalMergeVisitor, Token,
+        TokenData, TokenSeparator, TokenSimilarity, Utf8Byte,
     },
-    segment::{Segment, SegmentKey},
-    token::AlignmentTokenMergeVisitor,
+    vocab::Vocabulary,
 };
 
 #[derive(Clone, Debug)]
-pub struct StringCombiner {
+pub struct StringCombiner<K = Duration> {
     pub allow_token_deletion: bool,
+    pub budget: Budget,
     pub gap_extend: i32,
     pub gap_open: i32,
+    pub gap_policy: GapPolicy,
+    pub limits: Limits,
+    pub loop_suppression: Option<LoopSuppression>,
+    pub normalization: Normalization,
+    pub overlap: SegmentOverlap,
+    pub parallelism: Parallelism,
+    pub phrase_filter: Option<PhraseFilter>,
+    // NOTE: order the segments by their `SegmentKind::Normal` metadata
+    pub reorder_segments: bool,
     pub threshold_deletion_x: usize,
     pub threshold_deletion_y: usize,
+    // NOTE: the time key of the segments, see `with_time_key`
+    pub time_key: PhantomData<fn() -> K>,
+    // NOTE: give partial credit to the similar tokens (e.g. Hangul jamo), see `token_scoring`;
+    // the plain entry points cannot compare the tokens, so they reject it
+    pub token_similarity: bool,
 }
 
+// NOTE: only for `Duration`, so that the callers need not name the time key
 impl Default for StringCombiner {
     fn default() -> Self {
         Self {
             allow_token_deletion: true,
+            budget: Budget::unlimited(),
             gap_extend: -1,
             gap_open: -5,
+            gap_policy: GapPolicy::default(),
+            limits: Limits::unlimited(),
+            loop_suppression: None,
+            normalization: Normalization::default(),
+            overlap: SegmentOverlap::default(),
+            parallelism: Parallelism::new(),
+            phrase_filter: None,
+            reorder_segments: false,
             threshold_deletion_x: usize::MAX,
             threshold_deletion_y: 3,
+            time_key: PhantomData,
+            token_similarity: false,
         }
     }
 }
 
-impl StringCombiner {
+impl<K> StringCombiner<K>
+where
+    K: TimeKey,
+{
+    pub fn with_time_key<K2>(self) -> StringCombiner<K2>
+    where
+        K2: TimeKey,
+    {
+        StringCombiner {
+            allow_token_deletion: self.allow_token_deletion,
+            budget: self.budget,
+            gap_extend: self.gap_extend,
+            gap_open: self.gap_open,
+            gap_policy: self.gap_policy,
+            limits: self.limits,
+            loop_suppression: self.loop_suppression,
+            normalization: self.normalization,
+            overlap: self.overlap,
+            parallelism: self.parallelism,
+            phrase_filter: self.phrase_filter,
+            reorder_segments: self.reorder_segments,
+            threshold_deletion_x: self.threshold_deletion_x,
+            threshold_deletion_y: self.threshold_deletion_y,
+            time_key: PhantomData,
+            token_similarity: self.token_similarity,
+        }
+    }
+
     #[inline]
-    pub fn concat_segments<I, T, IT>(&self, inputs: I) -> Option<Segment<SegmentKey, Vec<T>>>
+    pub fn concat_segments<I, T, IT>(&self, inputs: I) -> Option<Segment<SegmentKey<K>, Vec<T>>>
     where
-        I: IntoIterator<Item = Segment<SegmentKey, IT>>,
+        I: IntoIterator<Item = Segment<SegmentKey<K>, IT>>,
         IT: IntoIterator<Item = T>,
-        T: Send + Sync + Clone + Eq,
+        T: Send + Sync + Clone + Eq + TokenSeparator,
     {
-        self.concat_segments_raw::<_, _, _>(inputs)
-            .map(|Segment { key, value }| Segment {
-                key,
-                value: value.value.into_iter().map(|token| token.data).collect(),
-            })
+        self.combine_segments(inputs).ok()?.value
     }
 
-    pub fn concat_segments_raw<I, T, IT>(
+    #[inline]
+    pub fn concat_segment_values<I>(
         &self,
         inputs: I,
-    ) -> Option<Segment<SegmentKey, AlignedSequence<T>>>
+        vocab: &Vocabulary,
+    ) -> Option<TimedSegment<SegmentValue, K>>
     where
-        I: IntoIterator<Item = Segment<SegmentKey, IT>>,
+        I: IntoIterator<Item = Segment<SegmentKey<K>, SegmentValue>>,
+    {
+        self.combine_segment_values(inputs, vocab).ok()?.value
+    }
+
+    #[inline]
+    pub fn concat_segment_values_by<I, F, T, IT>(
+        &self,
+        inputs: I,
+        f: F,
+    ) -> Option<TimedSegment<Vec<T>, K>>
+    where
+        I: IntoIterator<Item = Segment<SegmentKey<K>, SegmentValue>>,
+        F: FnMut(SegmentValue) -> IT,
         IT: IntoIterator<Item = T>,
-        T: Send + Sync + Clone + Eq,
+        T: Send + Sync + Clone + Eq + Token,
     {
-        fn match_fn<T>(
-            a: &Segment<SegmentKey, AlignedSequence<T>>,
-            b: &Segment<SegmentKey, AlignedSequence<T>>,
-        ) -> SequenceMatch<Segment<SegmentKey, AlignedSequence<T>>>
-        where
-            T: Clone,
-        {
-            if a.key.t1 > b.key.t0 {
-                SequenceMatch::Matched
-            } else {
-                SequenceMatch::Custom(Segment {
-                    key: SegmentKey {
-                        t0: a.key.t0.min(b.key.t0),
-                        t1: a.key.t1.max(b.key.t1),
-                    },
-                    value: a.value.join(&b.value, None),
-                })
-            }
-        }
+        self.combine_segment_values_by(inputs, f).ok()?.value
+    }
 
-        let inputs = inputs.into_iter().map(|Segment { key, value }| Segment {
-            key,
-            value: AlignedSequence::from_iter(value),
-        });
-        self.concat_with(inputs, match_fn)
+    #[inline]
+    pub fn concat_graphemes<I>(&self, inputs: I) -> Option<String>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        self.combine_graphemes(inputs).ok()?.value
+    }
+
+    #[inline]
+    pub fn concat_hierarchical<I>(&self, inputs: I) -> Option<String>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        self.combine_hierarchical(inputs).ok()?.value
+    }
+
+    #[inline]
+    pub fn concat_normalized<I>(&self, inputs: I) -> Option<String>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        self.combine_normalized(inputs).ok()?.value
+    }
+
+    #[inline]
+    pub fn concat_segment_tokens<I>(
+        &self,
+        inputs: I,
+        vocab: &Vocabulary,
+    ) -> Option<TimedSegment<Vec<TokenData>, K>>
+    where
+        I: IntoIterator<Item = Segment<SegmentKey<K>, SegmentValue>>,
+    {
+        self.combine_segment_tokens(inputs, vocab).ok()?.value
+    }
+
+    #[inline]
+    pub fn concat_segments_split<I, T, IT>(
+        &self,
+        inputs: I,
+        split: &SegmentSplit,
+    ) -> Option<Segments<T, K>>
+    where
+        I: IntoIterator<Item = Segment<SegmentKey<K>, IT>>,
+        IT: IntoIterator<Item = T>,
+        T: Send + Sync + Clone + Eq + Token,
+    {
+        self.combine_segments_split(inputs, split).ok()?.value
+    }
+
+    #[inline]
+    pub fn concat_segments_raw<I, T, IT>(
+        &self,
+        inputs: I,
+    ) -> Option<Segment<SegmentKey<K>, AlignedSequence<T>>>
+    where
+        I: IntoIterator<Item = Segment<SegmentKey<K>, IT>>,
+        IT: IntoIterator<Item = T>,
+        T: Send + Sync + Clone + Eq + TokenSeparator,
+    {
+        self.combine_segments_raw(inputs).ok()?.value
     }
 
+    #[inline]
     pub fn concat_strings<I, T>(&self, inputs: I) -> Option<String>
     where
         AlignedSequence<T>: fmt::Display,
@@ -92,18 +230,52 @@ impl StringCombiner {
         <I as IntoIterator>::Item: IntoIterator<Item = T>,
         T: Send + Sync + Clone + Eq,
     {
-        fn match_fn<T>(
-            _a: &AlignedSequence<T>,
-            _b: &AlignedSequence<T>,
-        ) -> SequenceMatch<AlignedSequence<T>> {
-            SequenceMatch::Matched
-        }
+        self.combine_strings(inputs).ok()?.value
+    }
 
-        let inputs = inputs.into_iter().map(AlignedSequence::from_iter);
-        self.concat_with(inputs, match_fn)
-            .map(|seq| seq.to_string())
+    #[inline]
+    pub fn concat_tokens<I, T>(&self, inputs: I) -> Option<String>
+    where
+        AlignedSequence<T>: fmt::Display,
+        I: IntoIterator,
+        <I as IntoIterator>::Item: IntoIterator<Item = T>,
+        T: Send + Sync + Clone + Eq + Token,
+    {
+        self.combine_tokens(inputs).ok()?.value
+    }
+
+    #[inline]
+    pub fn concat_token_segments<I, T, IT>(
+        &self,
+        inputs: I,
+    ) -> Option<Segment<SegmentKey<K>, Vec<T>>>
+    where
+        I: IntoIterator<Item = Segment<SegmentKey<K>, IT>>,
+        IT: IntoIterator<Item = T>,
+        T: Send + Sync + Clone + Eq + Token,
+    {
+        self.combine_token_segments(inputs).ok()?.value
+    }
+
+    #[inline]
+    pub fn concat_utf8<I>(&self, inputs: I) -> Option<String>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        self.combine_utf8(inputs).ok()?.value
+    }
+
+    #[inline]
+    pub fn concat_words<I>(&self, inputs: I) -> Option<String>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        self.combine_words(inputs).ok()?.value
     }
 
+    #[inline]
     pub fn concat_with<I, T, F>(&self, inputs: I, match_fn: F) -> Option<I::Item>
     where
         F: Sync + Fn(&I::Item, &I::Item) -> SequenceMatch<I::Item>,
@@ -117,98 +289,2216 @@ impl StringCombiner {
             + GreedyMultipleSequenceAlignerItem<T>,
         T: Send + Sync + Clone + Eq,
     {
-        let score = |a: &AlignedToken<_>, b: &AlignedToken<_>| if a == b { 2i32 } else { -3i32 };
-        let scoring = Scoring::new(self.gap_open, self.gap_extend, score);
+        self.combine_with(inputs, match_fn).ok()?.value
+    }
 
-        let score_fn = |s: &I::Item| -> Option<usize> {
-            let s: &AlignedSequence<T> = s.as_ref();
-            if s.num_deleted_x <= self.threshold_deletion_x
-                && s.num_deleted_y <= self.threshold_deletion_y
-            {
-                Some(s.total_matched())
-            } else {
-                None
-            }
+    #[inline]
+    pub fn concat_with_pool<I, T, F, Fscore>(
+        &self,
+        pool: &AlignerPool<Fscore, AlignedToken<T>>,
+        inputs: I,
+        match_fn: F,
+    ) -> Option<I::Item>
+    where
+        F: Sync + Fn(&I::Item, &I::Item) -> SequenceMatch<I::Item>,
+        Fscore: TokenScoreFn<T>,
+        I: IntoIterator,
+        I::Item: Send
+            + Sync
+            + Clone
+            + AsRef<AlignedSequence<T>>
+            + AsRef<[AlignedToken<T>]>
+            + AsRef<I::Item>
+            + GreedyMultipleSequenceAlignerItem<T>,
+        T: Send + Sync + Clone + Eq,
+    {
+        self.combine_with_pool(pool, inputs, match_fn).ok()?.value
+    }
+
+    pub fn combine_segment_values<I>(
+        &self,
+        inputs: I,
+        vocab: &Vocabulary,
+    ) -> Result<Combined<TimedSegment<SegmentValue, K>>, CombineError>
+    where
+        I: IntoIterator<Item = Segment<SegmentKey<K>, SegmentValue>>,
+    {
+        let mut kind = None;
+        let inputs = inputs.into_iter().inspect(|segment| {
+            let value = segment.kind.clone();
+            kind = Some(match kind.take() {
+                Some(kind) => SegmentKind::merge(kind, value),
+                None => value,
+            });
+        });
+
+        let combined = self.combine_segment_tokens(inputs, vocab)?;
+        Ok(combined.map(|Segment { key, value: tokens }| Segment {
+            key,
+            value: SegmentValue {
+                kind: kind.unwrap_or(SegmentKind::Selected),
+                // NOTE: the text is always the decoding of the tokens
+                text: vocab.decode_text(&tokens),
+                tokens,
+            },
+        }))
+    }
+
+    #[inline]
+    pub fn combine_segment_values_by<I, F, T, IT>(
+        &self,
+        inputs: I,
+        f: F,
+    ) -> Result<Combined<TimedSegment<Vec<T>, K>>, LimitError>
+    where
+        I: IntoIterator<Item = Segment<SegmentKey<K>, SegmentValue>>,
+        F: FnMut(SegmentValue) -> IT,
+        IT: IntoIterator<Item = T>,
+        T: Send + Sync + Clone + Eq + Token,
+    {
+        self.combine_segment_values_raw(inputs, None, f)
+    }
+
+    fn combine_segment_values_raw<I, F, T, IT>(
+        &self,
+        inputs: I,
+        vocab: Option<&Vocabulary>,
+        mut f: F,
+    ) -> Result<Combined<TimedSegment<Vec<T>, K>>, LimitError>
+    where
+        I: IntoIterator<Item = Segment<SegmentKey<K>, SegmentValue>>,
+        F: FnMut(SegmentValue) -> IT,
+        IT: IntoIterator<Item = T>,
+        T: Send + Sync + Clone + Eq + Token,
+    {
+        let mut sequence = Vec::new();
+        let inputs = if self.reorder_segments {
+            order_segments(inputs, &mut sequence)
+        } else {
+            inputs.into_iter().enumerate().collect()
         };
+        let indices: Vec<_> = inputs.iter().map(|(index, _)| *index).collect();
 
-        let aligner = GreedyMultipleSequenceAligner::new(match_fn, score_fn);
-        let visitor = AlignmentTokenMergeVisitor::new(self.allow_token_deletion);
-        aligner.reduce_all(scoring, visitor, inputs)
+        // Remove the known hallucinations before merging
+        let mut phrases = Vec::new();
+        let mut unmatched_phrases = Vec::new();
+        let inputs = inputs.into_iter().map(|(index, mut segment)| {
+            if let Some(phrase_filter) = &self.phrase_filter {
+                phrases.extend(phrase_filter.filter(
+                    index,
+                    &mut segment,
+                    vocab,
+                    &mut unmatched_phrases,
+                ));
+            }
+            let Segment { key, value } = segment;
+
+            // The selected segments are authoritative
+            let anchored = matches!(value.kind, SegmentKind::Selected);
+            Segment {
+                key,
+                value: f(value)
+                    .into_iter()
+                    .map(move |data| Anchored { anchored, data }),
+            }
+        });
+
+        let mut combined = self
+            .combine_token_segments(inputs)
+            .map_err(|mut error| {
+                error.event.index = indices[error.event.index];
+                error
+            })?
+            .map(|Segment { key, value }| Segment {
+                key,
+                value: value.into_iter().map(|token| token.data).collect(),
+            });
+        combined.report.phrases = phrases;
+        combined.report.sequence = sequence;
+        combined.report.unmatched_phrases = unmatched_phrases;
+        combined.report.restore_indices(&indices);
+        Ok(combined)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::StringCombiner;
+    pub fn combine_graphemes<I>(&self, inputs: I) -> Result<Combined<String>, LimitError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let inputs = inputs
+            .into_iter()
+            .map(|input| Grapheme::split(input.as_ref()).collect::<Vec<_>>());
+        self.combine_tokens(inputs)
+    }
 
-    #[test]
-    fn test_iter_empty() {
-        let inputs: Vec<Vec<char>> = vec![];
-        let combiner = StringCombiner::default();
-        let expected = None;
-        let combined = combiner.concat_strings(inputs);
-        assert_eq!(expected, combined.as_deref())
+    pub fn combine_hierarchical<I>(&self, inputs: I) -> Result<Combined<String>, LimitError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        fn match_fn(
+            _a: &AlignedSequence<String>,
+            _b: &AlignedSequence<String>,
+        ) -> SequenceMatch<AlignedSequence<String>> {
+            SequenceMatch::Matched
+        }
+
+        let pool = self.token_aligner_pool();
+        let char_pool = self.token_aligner_pool();
+        // NOTE: the character realignments are charged to the same budget
+        let num_cells = Arc::new(AtomicUsize::new(0));
+        let visitor = HierarchicalMergeVisitor::new(self.allow_token_deletion, &char_pool)
+            .with_budget(self.budget, num_cells.clone())
+            .with_limits(self.limits);
+
+        let inputs = inputs.into_iter().map(|input| {
+            input
+                .as_ref()
+                .split_word_bounds()
+                .map(String::from)
+                .collect::<AlignedSequence<_>>()
+        });
+        let mut combined =
+            self.combine_with_visitor(&pool, visitor, inputs, match_fn, Some(num_cells))?;
+        self.suppress_loops(&mut combined, LoopEvidence::from_timing);
+        Ok(combined.map(|seq| seq.to_string()))
     }
 
-    #[test]
-    fn test_iter_single() {
-        let inputs = vec!["Hello World".chars()];
-        let combiner = StringCombiner::default();
-        let expected = Some("Hello World");
-        let combined = combiner.concat_strings(inputs);
-        assert_eq!(expected, combined.as_deref())
+    pub fn combine_normalized<I>(&self, inputs: I) -> Result<Combined<String>, LimitError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let inputs = inputs
+            .into_iter()
+            .map(|input| self.normalization.normalize(input.as_ref()));
+        self.combine_tokens(inputs)
     }
 
-    #[test]
-    fn test_iter_multiple() {
-        let inputs = vec!["Hello World".chars(), "World!".chars()];
-        let combiner = StringCombiner::default();
-        let expected = Some("Hello World!");
-        let combined = combiner.concat_strings(inputs);
-        assert_eq!(expected, combined.as_deref())
+    pub fn combine_segment_tokens<I>(
+        &self,
+        inputs: I,
+        vocab: &Vocabulary,
+    ) -> Result<Combined<TimedSegment<Vec<TokenData>, K>>, CombineError>
+    where
+        I: IntoIterator<Item = Segment<SegmentKey<K>, SegmentValue>>,
+    {
+        // Align on the decoded bytes, as the chunks may be tokenized differently
+        let Combined { value, report } =
+            self.combine_segment_values_raw(inputs, Some(vocab), |value| {
+                vocab.decode(&value.tokens)
+            })?;
+        let value = match value {
+            Some(Segment { key, value }) => Some(Segment {
+                key,
+                value: vocab.encode(&value)?,
+            }),
+            None => None,
+        };
+        Ok(Combined { value, report })
     }
 
-    #[test]
-    fn test_errorous_inputs_binary() {
-        let inputs = vec!["Hello World".chars(), "world!".chars()];
-        let combiner = StringCombiner::default();
-        let expected = Some("Hello World!");
-        let combined = combiner.concat_strings(inputs);
-        assert_eq!(expected, combined.as_deref())
+    pub fn combine_segments<I, T, IT>(
+        &self,
+        inputs: I,
+    ) -> Result<Combined<TimedSegment<Vec<T>, K>>, LimitError>
+    where
+        I: IntoIterator<Item = Segment<SegmentKey<K>, IT>>,
+        IT: IntoIterator<Item = T>,
+        T: Send + Sync + Clone + Eq + TokenSeparator,
+    {
+        self.combine_segments_raw(inputs).map(|combined| {
+            combined.map(|Segment { key, value }| Segment {
+                key,
+                value: value.value.into_iter().map(|token| token.data).collect(),
+            })
+        })
     }
 
-    #[test]
-    fn test_errorous_inputs_keep_left() {
-        let inputs = vec![
-            "Hello World".chars(),
-            "Hello world".chars(),
-            "world".chars(),
-            "world!".chars(),
-            "world! My name is".chars(),
-            "world! My name is Ho Kim.".chars(),
-        ];
-        let combiner = StringCombiner::default();
-        let expected = Some("Hello World! My name is Ho Kim.");
-        let combined = combiner.concat_strings(inputs);
-        assert_eq!(expected, combined.as_deref())
+    pub fn combine_segments_split<I, T, IT>(
+        &self,
+        inputs: I,
+        split: &SegmentSplit,
+    ) -> Result<Combined<Segments<T, K>>, LimitError>
+    where
+        I: IntoIterator<Item = Segment<SegmentKey<K>, IT>>,
+        IT: IntoIterator<Item = T>,
+        T: Send + Sync + Clone + Eq + Token,
+    {
+        let mut edges = Vec::new();
+        let inputs = inputs
+            .into_iter()
+            .inspect(|segment| edges.push(segment.key.t1));
+
+        let combined = self.combine_token_segments(inputs)?;
+        Ok(combined.map(|segment| split.split(segment, &edges)))
     }
 
-    #[test]
-    fn test_errorous_inputs_shift() {
-        let inputs = vec![
-            "Hello World".chars(),
-            "Hello world".chars(),
-            "world".chars(),
+    pub fn combine_segments_raw<I, T, IT>(
+        &self,
+        inputs: I,
+    ) -> Result<Combined<TimedSegment<AlignedSequence<T>, K>>, LimitError>
+    where
+        I: IntoIterator<Item = Segment<SegmentKey<K>, IT>>,
+        IT: IntoIterator<Item = T>,
+        T: Send + Sync + Clone + Eq + TokenSeparator,
+    {
+        let overlap = self.overlap;
+        let match_fn = |a: &TimedSegment<AlignedSequence<T>, K>,
+                        b: &TimedSegment<AlignedSequence<T>, K>| {
+            if overlap.is_overlapped((a.key.t0, a.key.t1), (b.key.t0, b.key.t1)) {
+                SequenceMatch::Matched
+            } else {
+                // NOTE: the plain tokens are untimed, so the gap is measured from the keys
+                let gap = a.key.t1.elapsed(&b.key.t0);
+                let separator = self.separator(gap, (Duration::ZERO, gap));
+                SequenceMatch::Custom(join_segments(a, b, separator.as_deref()))
+            }
+        };
+
+        let inputs = inputs.into_iter().map(|Segment { key, value }| Segment {
+            key,
+            value: AlignedSequence::from_iter(value),
+        });
+        self.combine_with(inputs, match_fn)
+    }
+
+    pub fn combine_strings<I, T>(&self, inputs: I) -> Result<Combined<String>, LimitError>
+    where
+        AlignedSequence<T>: fmt::Display,
+        I: IntoIterator,
+        <I as IntoIterator>::Item: IntoIterator<Item = T>,
+        T: Send + Sync + Clone + Eq,
+    {
+        fn match_fn<T>(
+            _a: &AlignedSequence<T>,
+            _b: &AlignedSequence<T>,
+        ) -> SequenceMatch<AlignedSequence<T>> {
+            SequenceMatch::Matched
+        }
+
+        let inputs = inputs.into_iter().map(AlignedSequence::from_iter);
+        self.combine_with(inputs, match_fn)
+            .map(|combined| combined.map(|seq| seq.to_string()))
+    }
+
+    pub fn combine_tokens<I, T>(&self, inputs: I) -> Result<Combined<String>, LimitError>
+    where
+        AlignedSequence<T>: fmt::Display,
+        I: IntoIterator,
+        <I as IntoIterator>::Item: IntoIterator<Item = T>,
+        T: Send + Sync + Clone + Eq + Token,
+    {
+        fn match_fn<T>(
+            _a: &AlignedSequence<T>,
+            _b: &AlignedSequence<T>,
+        ) -> SequenceMatch<AlignedSequence<T>> {
+            SequenceMatch::Matched
+        }
+
+        let pool = self.token_aligner_pool();
+        let inputs = inputs.into_iter().map(AlignedSequence::from_iter);
+        self.combine_tokens_with_pool(&pool, inputs, match_fn, LoopEvidence::from_timing)
+            .map(|combined| combined.map(|seq| seq.to_string()))
+    }
+
+    pub fn combine_token_segments<I, T, IT>(
+        &self,
+        inputs: I,
+    ) -> Result<Combined<TimedSegment<Vec<T>, K>>, LimitError>
+    where
+        I: IntoIterator<Item = Segment<SegmentKey<K>, IT>>,
+        IT: IntoIterator<Item = T>,
+        T: Send + Sync + Clone + Eq + Token,
+    {
+        let pool = self.token_aligner_pool();
+        self.combine_token_segments_with_pool(&pool, inputs)
+    }
+
+    pub fn combine_token_segments_with_pool<I, T, IT, Fscore>(
+        &self,
+        pool: &AlignerPool<Fscore, AlignedToken<T>>,
+        inputs: I,
+    ) -> Result<Combined<TimedSegment<Vec<T>, K>>, LimitError>
+    where
+        Fscore: TokenScoreFn<T>,
+        I: IntoIterator<Item = Segment<SegmentKey<K>, IT>>,
+        IT: IntoIterator<Item = T>,
+        T: Send + Sync + Clone + Eq + Token,
+    {
+        self.combine_token_segments_raw(pool, inputs)
+            .map(|combined| {
+                combined.map(|Segment { key, value }| Segment {
+                    key,
+                    value: value.value.into_iter().map(|token| token.data).collect(),
+                })
+            })
+    }
+
+    fn combine_token_segments_raw<I, T, IT, Fscore>(
+        &self,
+        pool: &AlignerPool<Fscore, AlignedToken<T>>,
+        inputs: I,
+    ) -> Result<Combined<TimedSegment<AlignedSequence<T>, K>>, LimitError>
+    where
+        Fscore: TokenScoreFn<T>,
+        I: IntoIterator<Item = Segment<SegmentKey<K>, IT>>,
+        IT: IntoIterator<Item = T>,
+        T: Send + Sync + Clone + Eq + Token,
+    {
+        let overlap = self.overlap;
+        let span = |segment: &TimedSegment<AlignedSequence<T>, K>| {
+            if overlap.token_spans {
+                segment.span()
+            } else {
+                (segment.key.t0, segment.key.t1)
+            }
+        };
+        let match_fn = |a: &TimedSegment<AlignedSequence<T>, K>,
+                        b: &TimedSegment<AlignedSequence<T>, K>| {
+            let (a_span, b_span) = (span(a), span(b));
+            if overlap.is_overlapped(a_span, b_span) {
+                SequenceMatch::Matched
+            } else {
+                // Tell apart the separate utterances
+                let gap = a_span.1.elapsed(&b_span.0);
+                let gap_span = match (span_of(&a.value.value), span_of(&b.value.value)) {
+                    (Some((_, t0)), Some((t1, _))) => (t0, t1.max(t0)),
+                    _ => (Duration::ZERO, gap),
+                };
+                let separator = self.separator(gap, gap_span);
+                SequenceMatch::Custom(join_segments(a, b, separator.as_deref()))
+            }
+        };
+
+        let mut keys = Vec::new();
+        let inputs: Vec<_> = inputs
+            .into_iter()
+            .map(|Segment { key, value }| {
+                keys.push((key.t0, key.t1));
+                Segment {
+                    key,
+                    value: AlignedSequence::from_iter(value),
+                }
+            })
+            .collect();
+
+        // The audio transcribed by the overlapping segments may be repeated in the merge
+        keys.sort();
+        let windows: Vec<_> = keys
+            .iter()
+            .enumerate()
+            .flat_map(|(index, &(t0, t1))| {
+                keys[index + 1..]
+                    .iter()
+                    .take_while(move |&&(next_t0, _)| next_t0 < t1)
+                    .map(move |&(next_t0, next_t1)| (t0.max(next_t0), t1.min(next_t1)))
+            })
+            .collect();
+        let evidence = |last: &[AlignedToken<T>], copy: &[AlignedToken<T>]| {
+            LoopEvidence::from_timing(last, copy).or_else(|| {
+                let (t0, _) = span_of(last)?;
+                let (_, t1) = span_of(copy)?;
+                let (t0, t1) = (K::from_timestamp(t0)?, K::from_timestamp(t1)?);
+                windows
+                    .iter()
+                    .any(|&(w0, w1)| w0 <= t0 && t1 <= w1)
+                    .then_some(LoopEvidence::Artifact)
+            })
+        };
+        self.combine_tokens_with_pool(pool, inputs, match_fn, evidence)
+    }
+
+    pub fn combine_utf8<I>(&self, inputs: I) -> Result<Combined<String>, LimitError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let inputs = inputs
+            .into_iter()
+            .map(|input| Utf8Byte::split(input.as_ref()).collect::<Vec<_>>());
+        self.combine_tokens(inputs)
+    }
+
+    pub fn combine_words<I>(&self, inputs: I) -> Result<Combined<String>, LimitError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        // NOTE: the words are hardly equal to each other, so the similar ones are matched
+        let combiner = Self {
+            token_similarity: true,
+            ..self.clone()
+        };
+
+        // Keep the original spacing as tokens
+        let inputs = inputs.into_iter().map(|input| {
+            input
+                .as_ref()
+                .split_word_bounds()
+                .map(Into::into)
+                .collect::<Vec<String>>()
+        });
+        combiner.combine_tokens(inputs)
+    }
+
+    pub fn combine_with<I, T, F>(
+        &self,
+        inputs: I,
+        match_fn: F,
+    ) -> Result<Combined<I::Item>, LimitError>
+    where
+        F: Sync + Fn(&I::Item, &I::Item) -> SequenceMatch<I::Item>,
+        I: IntoIterator,
+        I::Item: Send
+            + Sync
+            + Clone
+            + AsRef<AlignedSequence<T>>
+            + AsRef<[AlignedToken<T>]>
+            + AsRef<I::Item>
+            + GreedyMultipleSequenceAlignerItem<T>,
+        T: Send + Sync + Clone + Eq,
+    {
+        let pool = self.aligner_pool();
+        self.combine_with_pool(&pool, inputs, match_fn)
+    }
+
+    pub fn combine_with_pool<I, T, F, Fscore>(
+        &self,
+        pool: &AlignerPool<Fscore, AlignedToken<T>>,
+        inputs: I,
+        match_fn: F,
+    ) -> Result<Combined<I::Item>, LimitError>
+    where
+        F: Sync + Fn(&I::Item, &I::Item) -> SequenceMatch<I::Item>,
+        Fscore: TokenScoreFn<T>,
+        I: IntoIterator,
+        I::Item: Send
+            + Sync
+            + Clone
+            + AsRef<AlignedSequence<T>>
+            + AsRef<[AlignedToken<T>]>
+            + AsRef<I::Item>
+            + GreedyMultipleSequenceAlignerItem<T>,
+        T: Send + Sync + Clone + Eq,
+    {
+        let visitor = AlignmentTokenMergeVisitor::new(self.allow_token_deletion);
+        let mut combined = self.combine_with_visitor(pool, visitor, inputs, match_fn, None)?;
+
+        // NOTE: the plain tokens are untimed, so the loops are only reported
+        if let (Some(loop_suppression), Some(value)) = (&self.loop_suppression, &combined.value) {
+            let mut seq = AsRef::<AlignedSequence<T>>::as_ref(value).clone();
+            combined.report.loops = loop_suppression.suppress_by(&mut seq, |_, _| None);
+        }
+        Ok(combined)
+    }
+
+    fn combine_tokens_with_pool<I, T, F, Fe, Fscore>(
+        &self,
+        pool: &AlignerPool<Fscore, AlignedToken<T>>,
+        inputs: I,
+        match_fn: F,
+        evidence: Fe,
+    ) -> Result<Combined<I::Item>, LimitError>
+    where
+        F: Sync + Fn(&I::Item, &I::Item) -> SequenceMatch<I::Item>,
+        Fe: Fn(&[AlignedToken<T>], &[AlignedToken<T>]) -> Option<LoopEvidence>,
+        Fscore: TokenScoreFn<T>,
+        I: IntoIterator,
+        I::Item: Send
+            + Sync
+            + Clone
+            + AsRef<AlignedSequence<T>>
+            + AsMut<AlignedSequence<T>>
+            + AsRef<[AlignedToken<T>]>
+            + AsRef<I::Item>
+            + GreedyMultipleSequenceAlignerItem<T>,
+        T: Send + Sync + Clone + Eq + Token,
+    {
+        let visitor = AlignmentTokenMergeVisitor::with_token_merge(self.allow_token_deletion);
+        let mut combined = self.combine_with_visitor(pool, visitor, inputs, match_fn, None)?;
+        self.suppress_loops(&mut combined, evidence);
+        Ok(combined)
+    }
+
+    fn combine_with_visitor<I, T, F, Fscore, V>(
+        &self,
+        pool: &AlignerPool<Fscore, AlignedToken<T>>,
+        visitor: V,
+        inputs: I,
+        match_fn: F,
+        num_cells: Option<Arc<AtomicUsize>>,
+    ) -> Result<Combined<I::Item>, LimitError>
+    where
+        F: Sync + Fn(&I::Item, &I::Item) -> SequenceMatch<I::Item>,
+        Fscore: TokenScoreFn<T>,
+        I: IntoIterator,
+        I::Item: Send
+            + Sync
+            + Clone
+            + AsRef<AlignedSequence<T>>
+            + AsRef<[AlignedToken<T>]>
+            + AsRef<I::Item>
+            + GreedyMultipleSequenceAlignerItem<T>,
+        T: Send + Sync + Clone + Eq,
+        V: Send + Sync + Clone + AlignmentVisitor<AlignedToken<T>, Output = AlignedSequence<T>>,
+    {
+        let score_fn = |s: &I::Item| -> Option<usize> {
+            let s: &AlignedSequence<T> = s.as_ref();
+            if s.num_deleted_x <= self.threshold_deletion_x
+                && s.num_deleted_y <= self.threshold_deletion_y
+            {
+                Some(s.total_matched())
+            } else {
+                None
+            }
+        };
+
+        let mut limits = Vec::new();
+        let (indices, inputs) = self.apply_limits(inputs, &mut limits)?;
+        let restore_index = |event: &mut LimitEvent| event.index = indices[event.index];
+
+        let mut aligner = GreedyMultipleSequenceAligner::new(match_fn, score_fn)
+            .with_budget(self.budget)
+            .with_limits(self.limits)
+            .with_parallelism(self.parallelism.clone());
+        aligner.num_cells = num_cells;
+        let (value, mut reduce) = aligner
+            .reduce_all_with_report(pool, visitor, inputs)
+            .map_err(|mut error| {
+                restore_index(&mut error.event);
+                error
+            })?;
+        reduce.limits.iter_mut().for_each(restore_index);
+
+        Ok(Combined {
+            value,
+            report: CombineReport {
+                limits,
+                loops: Vec::new(),
+                phrases: Vec::new(),
+                reduce,
+                sequence: Vec::new(),
+                unmatched_phrases: Vec::new(),
+            },
+        })
+    }
+
+    fn separator<T>(&self, gap: Duration, gap_span: (Duration, Duration)) -> Option<Vec<T>>
+    where
+        T: TokenSeparator,
+    {
+        self.gap_policy
+            .separator(gap)
+            .map(|separator| T::separator(separator, gap_span))
+    }
+
+    fn suppress_loops<Item, T, F>(&self, combined: &mut Combined<Item>, evidence: F)
+    where
+        Item: AsMut<AlignedSequence<T>>,
+        T: Clone + PartialEq,
+        F: Fn(&[AlignedToken<T>], &[AlignedToken<T>]) -> Option<LoopEvidence>,
+    {
+        if let (Some(loop_suppression), Some(value)) = (&self.loop_suppression, &mut combined.value)
+        {
+            combined.report.loops = loop_suppression.suppress_by(value.as_mut(), evidence);
+        }
+    }
+
+    fn apply_limits<I, T>(
+        &self,
+        inputs: I,
+        events: &mut Vec<LimitEvent>,
+    ) -> Result<(Vec<usize>, Vec<I::Item>), LimitError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<AlignedSequence<T>> + GreedyMultipleSequenceAlignerItem<T>,
+    {
+        let Limits {
+            max_cells: _,
+            max_segment_len,
+            max_total_len,
+            policy,
+        } = self.limits;
+
+        let mut indices = Vec::new();
+        let mut outputs = Vec::new();
+        let mut total_len = 0;
+        for (index, mut input) in inputs.into_iter().enumerate() {
+            let seq: &AlignedSequence<T> = input.as_ref();
+            let len = seq.value.len();
+
+            let mut max_len = len;
+            let mut event = None;
+            if let Some(limit) = max_segment_len.filter(|&limit| len > limit) {
+                max_len = max_len.min(limit);
+                event = Some(LimitEvent {
+                    index,
+                    kind: LimitKind::SegmentLen,
+                    len,
+                    limit,
+                    policy,
+                });
+            }
+            if let Some(limit) = max_total_len.filter(|&limit| total_len + max_len > limit) {
+                max_len = max_len.min(limit - total_len);
+                event = Some(LimitEvent {
+                    index,
+                    kind: LimitKind::TotalLen,
+                    len: total_len + len,
+                    limit,
+                    policy,
+                });
+            }
+
+            if let Some(mut event) = event {
+                // NOTE: the items which cannot be cut are skipped instead
+                if policy == LimitPolicy::Truncate && max_len > 0 && !input.truncate(max_len) {
+                    event.policy = LimitPolicy::Skip;
+                }
+                events.push(event);
+                match event.policy {
+                    LimitPolicy::Truncate if max_len > 0 => (),
+                    LimitPolicy::Truncate | LimitPolicy::Skip => continue,
+                    LimitPolicy::Error => return Err(LimitError { event }),
+                }
+            }
+            total_len += max_len;
+            indices.push(index);
+            outputs.push(input);
+        }
+        Ok((indices, outputs))
+    }
+
+    pub fn aligner_pool<T>(&self) -> AlignerPool<TokenScorer<T>, AlignedToken<T>>
+    where
+        T: Send + Clone + Eq,
+    {
+        AlignerPool::new(self.scoring())
+    }
+
+    pub fn token_aligner_pool<T>(&self) -> AlignerPool<TokenScorer<T>, AlignedToken<T>>
+    where
+        T: Send + Clone + Eq + TokenSimilarity,
+    {
+        AlignerPool::new(self.token_scoring())
+    }
+
+    pub fn scoring<T>(&self) -> Scoring<TokenScorer<T>, AlignedToken<T>>
+    where
+        T: Eq,
+    {
+        assert!(
+            !self.token_similarity,
+            "`token_similarity` requires the token entry points, see `token_scoring`",
+        );
+        Scoring::new(
+            self.gap_open,
+            self.gap_extend,
+            score_exact as TokenScorer<T>,
+        )
+    }
+
+    pub fn token_scoring<T>(&self) -> Scoring<TokenScorer<T>, AlignedToken<T>>
+    where
+        T: Eq + TokenSimilarity,
+    {
+        let score: TokenScorer<T> = if self.token_similarity {
+            score_similar
+        } else {
+            score_exact
+        };
+        Scoring::new(self.gap_open, self.gap_extend, score)
+    }
+}
+
+// NOTE: a plain function pointer, so that the pools can be named and stored
+pub type TokenScorer<T> = fn(&AlignedToken<T>, &AlignedToken<T>) -> i32;
+
+const MATCH: i32 = 2;
+const MISMATCH: i32 = -3;
+
+fn score_exact<T>(a: &AlignedToken<T>, b: &AlignedToken<T>) -> i32
+where
+    T: Eq,
+{
+    if a == b {
+        MATCH
+    } else {
+        MISMATCH
+    }
+}
+
+fn score_similar<T>(a: &AlignedToken<T>, b: &AlignedToken<T>) -> i32
+where
+    T: Eq + TokenSimilarity,
+{
+    if a == b {
+        MATCH
+    } else if let Some(similarity) = a.data.similarity(&b.data) {
+        MISMATCH + ((MATCH - MISMATCH) as f32 * similarity.clamp(0.0, 1.0)).round() as i32
+    } else {
+        MISMATCH
+    }
+}
+
+fn join_segments<K, T>(
+    a: &TimedSegment<AlignedSequence<T>, K>,
+    b: &TimedSegment<AlignedSequence<T>, K>,
+    separator: Option<&[T]>,
+) -> TimedSegment<AlignedSequence<T>, K>
+where
+    K: TimeKey,
+    T: Clone,
+{
+    Segment {
+        key: SegmentKey {
+            t0: a.key.t0.min(b.key.t0),
+            t1: a.key.t1.max(b.key.t1),
+        },
+        value: a.value.join(&b.value, separator),
+    }
+}
+
+pub trait TokenScoreFn<T>:
+    Send + Sync + Clone + Fn(&AlignedToken<T>, &AlignedToken<T>) -> i32
+{
+}
+
+impl<F, T> TokenScoreFn<T> for F where
+    F: Send + Sync + Clone + Fn(&AlignedToken<T>, &AlignedToken<T>) -> i32
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        hallucination::{LoopSuppression, Phrase, PhraseFilter, RemovalReason},
+        limit::{LimitKind, LimitPolicy, Limits},
+        msa::{
+            AlignedSequence, AlignedToken, AlignerPool, Budget, Degradation,
+            GreedyMultipleSequenceAlignerItem, SequenceMatch,
+        },
+        segment::{
+            GapPolicy, Segment, SegmentKey, SegmentKind, SegmentOverlap, SegmentSplit,
+            SegmentValue, Separator,
+        },
+        sequence::SequenceEvent,
+        session::{CombineSession, Insertion, Reorder, SegmentId},
+        time::{Ticks, TimeKey},
+        token::{TimedByte, TokenData, TokenSeparator, TokenSimilarity, Utf8Byte},
+        vocab::{EncodeError, Vocabulary},
+        StringCombiner, TokenScorer,
+    };
+
+    fn key(t0: u64, t1: u64) -> SegmentKey {
+        SegmentKey {
+            t0: Duration::from_millis(t0),
+            t1: Duration::from_millis(t1),
+        }
+    }
+
+    fn segment(t0: u64, t1: u64, text: &str) -> Segment<SegmentKey, Vec<char>> {
+        Segment {
+            key: key(t0, t1),
+            value: text.chars().collect(),
+        }
+    }
+
+    fn token(id: i32, t0: u64, t1: u64) -> TokenData {
+        TokenData {
+            id,
+            t0: Duration::from_millis(t0),
+            t1: Duration::from_millis(t1),
+        }
+    }
+
+    fn byte_vocab() -> Vocabulary {
+        (0..=u8::MAX).map(|b| (b.into(), vec![b])).collect()
+    }
+
+    // NOTE: one token per byte, timed to the whole segment
+    fn byte_segment(t0: u64, t1: u64, last_index: usize, text: &str) -> Segment {
+        Segment {
+            key: key(t0, t1),
+            value: SegmentValue {
+                kind: SegmentKind::Normal {
+                    last_index,
+                    last_offset: None,
+                    total_period: Duration::ZERO,
+                },
+                text: text.into(),
+                tokens: text.bytes().map(|b| token(b.into(), t0, t1)).collect(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_iter_empty() {
+        let inputs: Vec<Vec<char>> = vec![];
+        let combiner = StringCombiner::default();
+        let expected = None;
+        let combined = combiner.concat_strings(inputs);
+        assert_eq!(expected, combined.as_deref())
+    }
+
+    #[test]
+    fn test_iter_single() {
+        let inputs = vec!["Hello World".chars()];
+        let combiner = StringCombiner::default();
+        let expected = Some("Hello World");
+        let combined = combiner.concat_strings(inputs);
+        assert_eq!(expected, combined.as_deref())
+    }
+
+    #[test]
+    fn test_iter_multiple() {
+        let inputs = vec!["Hello World".chars(), "World!".chars()];
+        let combiner = StringCombiner::default();
+        let expected = Some("Hello World!");
+        let combined = combiner.concat_strings(inputs);
+        assert_eq!(expected, combined.as_deref())
+    }
+
+    #[test]
+    fn test_errorous_inputs_binary() {
+        let inputs = vec!["Hello World".chars(), "world!".chars()];
+        let combiner = StringCombiner::default();
+        let expected = Some("Hello World!");
+        let combined = combiner.concat_strings(inputs);
+        assert_eq!(expected, combined.as_deref())
+    }
+
+    #[test]
+    fn test_errorous_inputs_keep_left() {
+        let inputs = vec![
+            "Hello World".chars(),
+            "Hello world".chars(),
+            "world".chars(),
+            "world!".chars(),
+            "world! My name is".chars(),
+            "world! My name is Ho Kim.".chars(),
+        ];
+        let combiner = StringCombiner::default();
+        let expected = Some("Hello World! My name is Ho Kim.");
+        let combined = combiner.concat_strings(inputs);
+        assert_eq!(expected, combined.as_deref())
+    }
+
+    #[test]
+    fn test_errorous_inputs_shift() {
+        let inputs = vec![
+            "Hello World".chars(),
+            "Hello world".chars(),
+            "world".chars(),
             "world!".chars(),
             "world! My name is".chars(),
             "world! My name is Ho Kim.".chars(),
         ];
         let combiner = StringCombiner {
-            threshold_deletion_y: 0,
+            threshold_deletion_y: 0,
+            ..Default::default()
+        };
+        let expected = Some("Hello world! My name is Ho Kim.");
+        let combined = combiner.concat_strings(inputs);
+        assert_eq!(expected, combined.as_deref())
+    }
+
+    #[test]
+    fn test_aligner_pool_reuse() {
+        let combiner = StringCombiner::default();
+        let pool: AlignerPool<TokenScorer<char>, AlignedToken<char>> = combiner.aligner_pool();
+        let match_fn =
+            |_: &AlignedSequence<char>, _: &AlignedSequence<char>| SequenceMatch::Matched;
+
+        for _ in 0..2 {
+            let inputs = ["Hello World", "World!", "World! My name is"]
+                .into_iter()
+                .map(|s| s.chars().collect::<AlignedSequence<_>>());
+            let expected = Some("Hello World! My name is");
+            let combined = combiner.concat_with_pool(&pool, inputs, match_fn);
+            assert_eq!(expected, combined.map(|s| s.to_string()).as_deref());
+        }
+        assert!(!pool.is_empty());
+    }
+
+    #[test]
+    fn test_final_selection_tie() {
+        let combiner = StringCombiner::default();
+        let match_fn = |a: &AlignedSequence<char>, b: &AlignedSequence<char>| {
+            let has_x = |s: &AlignedSequence<char>| s.value.iter().any(|token| token.data == 'x');
+            if has_x(a) || has_x(b) {
+                SequenceMatch::Unmatched
+            } else {
+                SequenceMatch::Matched
+            }
+        };
+
+        // "ab" + "ab" scores the same as "xyzw", but comes later in the table
+        let inputs = ["ab", "xyzw", "ab"]
+            .into_iter()
+            .map(|s| s.chars().collect::<AlignedSequence<_>>());
+        let combined = combiner.concat_with(inputs, match_fn);
+        assert_eq!(Some("ab"), combined.map(|s| s.to_string()).as_deref());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_deterministic_across_threads() {
+        let inputs: Vec<_> = (0..64)
+            .map(|i| match i % 4 {
+                0 => "Hello World",
+                1 => "Hello world",
+                2 => "hello World",
+                _ => "Hello Worlds",
+            })
+            .collect();
+        let combiner = StringCombiner::default();
+
+        let expected = combiner.concat_strings(inputs.iter().map(|s| s.chars()));
+        for num_threads in [1, 2, 3, 8] {
+            let pool = ::rayon::ThreadPoolBuilder::new()
+                .num_threads(num_threads)
+                .build()
+                .unwrap();
+            let combined =
+                pool.install(|| combiner.concat_strings(inputs.iter().map(|s| s.chars())));
+            assert_eq!(expected, combined);
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_parallelism_empty_partition() {
+        use crate::msa::{ParallelGranularity, Parallelism};
+
+        let combiner = StringCombiner {
+            parallelism: Parallelism {
+                granularity: ParallelGranularity::Partition,
+                min_parallel_len: Some(0),
+                thread_pool: None,
+            },
+            ..Default::default()
+        };
+        let combined = combiner.concat_strings(["Hello World".chars()]);
+        assert_eq!(Some("Hello World"), combined.as_deref());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_parallelism_custom_thread_pool() {
+        use crate::msa::{ParallelGranularity, Parallelism};
+
+        let inputs: Vec<_> = (0..32)
+            .map(|i| if i % 2 == 0 { "Hello World" } else { "World!" })
+            .collect();
+        let thread_pool = ::rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .map(::std::sync::Arc::new)
+            .unwrap();
+
+        let expected = Some("Hello World!");
+        for granularity in [
+            ParallelGranularity::Candidate,
+            ParallelGranularity::Partition,
+            ParallelGranularity::Sequential,
+        ] {
+            let combiner = StringCombiner {
+                parallelism: Parallelism {
+                    granularity,
+                    min_parallel_len: Some(1),
+                    thread_pool: Some(thread_pool.clone()),
+                },
+                ..Default::default()
+            };
+            let combined = combiner.concat_strings(inputs.iter().map(|s| s.chars()));
+            assert_eq!(expected, combined.as_deref());
+        }
+    }
+
+    #[test]
+    fn test_budget_degradation() {
+        let inputs = vec![
+            "Hello World".chars(),
+            "World!".chars(),
+            "World! My name is".chars(),
+        ];
+        let combiner = StringCombiner {
+            budget: Budget {
+                // the first alignment alone uses 11 * 6 cells
+                max_cells: Some(22),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let expected = Some("Hello World! My name is");
+        let combined = combiner.combine_strings(inputs).unwrap();
+        assert_eq!(expected, combined.value.as_deref());
+        assert_eq!(
+            Some(Degradation::ExactOverlap),
+            combined.report.reduce.degradation
+        );
+        assert_eq!(66, combined.report.reduce.num_cells);
+    }
+
+    #[test]
+    fn test_limits_segment_len() {
+        let inputs = vec!["Hello World".chars(), "World! My name is Ho Kim.".chars()];
+        let limits = Limits {
+            max_segment_len: Some(17),
+            ..Default::default()
+        };
+
+        let combiner = StringCombiner {
+            limits,
+            ..Default::default()
+        };
+        let expected = Some("Hello World! My name is");
+        let combined = combiner.combine_strings(inputs.clone()).unwrap();
+        assert_eq!(expected, combined.value.as_deref());
+        let events: Vec<_> = combined.report.applied_limits().collect();
+        assert_eq!(1, events.len());
+        assert_eq!(
+            (1, LimitKind::SegmentLen),
+            (events[0].index, events[0].kind)
+        );
+
+        let combiner = StringCombiner {
+            limits: Limits {
+                policy: LimitPolicy::Skip,
+                ..limits
+            },
+            ..Default::default()
+        };
+        let expected = Some("Hello World");
+        let combined = combiner.combine_strings(inputs.clone()).unwrap();
+        assert_eq!(expected, combined.value.as_deref());
+
+        let combiner = StringCombiner {
+            limits: Limits {
+                policy: LimitPolicy::Error,
+                ..limits
+            },
+            ..Default::default()
+        };
+        let error = combiner.combine_strings(inputs.clone()).unwrap_err();
+        assert_eq!(1, error.event.index);
+        assert_eq!(None, combiner.concat_strings(inputs));
+
+        // The end of the truncated segment is moved along with its tokens
+        let inputs = [Segment {
+            key: SegmentKey {
+                t0: Duration::ZERO,
+                t1: Duration::from_millis(1000),
+            },
+            value: "0123456789".chars(),
+        }];
+        let combiner = StringCombiner {
+            limits: Limits {
+                max_segment_len: Some(5),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let output = combiner.concat_segments(inputs).unwrap();
+        assert_eq!(Duration::from_millis(500), output.key.t1);
+    }
+
+    #[test]
+    fn test_limits_custom_item() {
+        #[derive(Clone)]
+        struct Item(AlignedSequence<char>);
+
+        impl AsRef<AlignedSequence<char>> for Item {
+            fn as_ref(&self) -> &AlignedSequence<char> {
+                &self.0
+            }
+        }
+
+        impl AsRef<[AlignedToken<char>]> for Item {
+            fn as_ref(&self) -> &[AlignedToken<char>] {
+                &self.0.value
+            }
+        }
+
+        impl AsRef<Self> for Item {
+            fn as_ref(&self) -> &Self {
+                self
+            }
+        }
+
+        impl GreedyMultipleSequenceAlignerItem<char> for Item {
+            fn build(_: &Self, _: &Self, seq: AlignedSequence<char>) -> Self {
+                Self(seq)
+            }
+        }
+
+        let inputs =
+            ["Hello World", "World! My name is Ho Kim."].map(|s| Item(s.chars().collect()));
+        let combiner = StringCombiner {
+            limits: Limits {
+                max_segment_len: Some(17),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // The items which cannot be cut are skipped
+        let combined = combiner
+            .combine_with(inputs, |_, _| SequenceMatch::Matched)
+            .unwrap();
+        assert_eq!("Hello World", combined.value.unwrap().0.to_string());
+        assert_eq!(LimitPolicy::Skip, combined.report.limits[0].policy);
+    }
+
+    #[test]
+    fn test_limits_cells_truncate() {
+        let inputs = vec![
+            "Lorem ipsum dolor sit amet, Hello World".chars(),
+            "World!".chars(),
+        ];
+        let combiner = StringCombiner {
+            limits: Limits {
+                max_cells: Some(60),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let expected = Some("Lorem ipsum dolor sit amet, Hello World!");
+        let combined = combiner.combine_strings(inputs).unwrap();
+        assert_eq!(expected, combined.value.as_deref());
+        assert_eq!(60, combined.report.reduce.num_cells);
+        assert_eq!(LimitKind::Cells, combined.report.reduce.limits[0].kind);
+
+        // The head of `y` is aligned alone if `y` is longer than the limit
+        let inputs = vec!["Hello World".chars(), "World! My name is".chars()];
+        let combiner = StringCombiner {
+            limits: Limits {
+                max_cells: Some(12),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let expected = Some("Hello World! My name is");
+        let combined = combiner.combine_strings(inputs).unwrap();
+        assert_eq!(expected, combined.value.as_deref());
+        assert_eq!(12, combined.report.reduce.num_cells);
+    }
+
+    #[test]
+    fn test_loop_suppression_hallucination() {
+        let combiner = StringCombiner {
+            loop_suppression: Some(LoopSuppression::default()),
+            ..Default::default()
+        };
+
+        // The untimed repetitions may be genuine, so they are only reported
+        let input = "실패했다 실패했다 실패했다 실패했다 아니";
+        let combined = combiner.combine_tokens([input.chars()]).unwrap();
+        assert_eq!(Some(input), combined.value.as_deref());
+        assert_eq!(2, combined.report.loops[0].num_unverified);
+        let combined = combiner.combine_strings(["ㅋㅋㅋㅋㅋㅋ".chars()]).unwrap();
+        assert_eq!(Some("ㅋㅋㅋㅋㅋㅋ"), combined.value.as_deref());
+        assert_eq!(1, combined.report.loops[0].num_unverified);
+
+        // The copies taking no time are removed over `max_repeats`
+        let mut seq: AlignedSequence<_> = [
+            token(1, 0, 100),
+            token(2, 100, 200),
+            token(1, 300, 400),
+            token(2, 400, 500),
+            token(1, 500, 500),
+            token(2, 500, 500),
+        ]
+        .into_iter()
+        .collect();
+        let loops = LoopSuppression::default().suppress(&mut seq);
+        assert_eq!(4, seq.value.len());
+        assert_eq!(1, loops[0].num_hallucinations);
+    }
+
+    #[test]
+    fn test_loop_suppression_segments() {
+        let segment = |t0, t1, tokens| Segment {
+            key: key(t0, t1),
+            value: tokens,
+        };
+        let inputs = [
+            segment(
+                0,
+                1000,
+                vec![
+                    token(5, 0, 100),
+                    token(1, 500, 600),
+                    token(2, 600, 700),
+                    token(1, 700, 800),
+                    token(2, 800, 900),
+                    token(1, 900, 950),
+                    token(2, 950, 1000),
+                ],
+            ),
+            segment(
+                500,
+                1500,
+                vec![token(1, 500, 600), token(2, 600, 700), token(9, 1100, 1200)],
+            ),
+        ];
+        let combiner = StringCombiner {
+            loop_suppression: Some(LoopSuppression::default()),
             ..Default::default()
         };
-        let expected = Some("Hello world! My name is Ho Kim.");
-        let combined = combiner.concat_strings(inputs);
-        assert_eq!(expected, combined.as_deref())
+
+        // The copies inside the audio of the two segments are the merge artifacts
+        let combined = combiner.combine_token_segments(inputs).unwrap();
+        let ids: Vec<_> = combined.value.unwrap().value.iter().map(|t| t.id).collect();
+        assert_eq!(vec![5, 1, 2, 9], ids);
+        assert_eq!(2, combined.report.loops[0].num_artifacts);
+    }
+
+    #[test]
+    fn test_loop_suppression_artifact() {
+        let mut seq: AlignedSequence<_> = [
+            token(1, 0, 100),
+            token(2, 100, 200),
+            // the same audio, transcribed twice
+            token(1, 50, 150),
+            token(2, 150, 250),
+            // a genuine repetition
+            token(1, 300, 400),
+            token(2, 400, 500),
+        ]
+        .into_iter()
+        .collect();
+
+        let loops = LoopSuppression::default().suppress(&mut seq);
+        assert_eq!(4, seq.value.len());
+        assert_eq!(1, loops[0].num_artifacts);
+        assert_eq!(0, loops[0].num_hallucinations);
+    }
+
+    #[test]
+    fn test_graphemes() {
+        let inputs = [
+            "\u{1f469}\u{200d}\u{1f469}\u{200d}\u{1f467} family",
+            "\u{1f469}\u{200d}\u{1f469}\u{200d}\u{1f466} family",
+            "\u{1f469}\u{200d}\u{1f469}\u{200d}\u{1f467} family",
+        ];
+        let combiner = StringCombiner::default();
+
+        let output = combiner.concat_graphemes(inputs);
+        assert_eq!(output.as_deref(), Some(inputs[0]));
+    }
+
+    #[test]
+    fn test_utf8() {
+        let inputs = ["한글 문장", "한굴 문장", "한글 문장"];
+        let combiner = StringCombiner::default();
+
+        let output = combiner.concat_utf8(inputs);
+        assert_eq!(output.as_deref(), Some("한글 문장"));
+
+        // The split characters are written as a whole
+        let seq: AlignedSequence<_> = Utf8Byte::split("한글")
+            .enumerate()
+            .filter(|&(index, _)| index != 4)
+            .map(|(_, token)| token)
+            .collect();
+        assert_eq!(seq.to_string(), "한글");
+
+        // The interleaved and duplicated bytes are written as a single character
+        let bytes = |text: &str, count| {
+            Utf8Byte::split(text)
+                .map(move |data| AlignedToken { count, data })
+                .collect::<Vec<_>>()
+        };
+        let (a, b) = (bytes("글", 2), bytes("굴", 1));
+        let value = (0..3).flat_map(|index| [a[index], b[index]]).collect();
+        let seq = AlignedSequence {
+            num_deleted_x: 0,
+            num_deleted_y: 0,
+            value,
+        };
+        assert_eq!(seq.to_string(), "글");
+        let (a, b) = (bytes("a한", 1), bytes("한b", 1));
+        let value = [&a[..3], &b[1..]].concat();
+        let seq = AlignedSequence {
+            num_deleted_x: 0,
+            num_deleted_y: 0,
+            value,
+        };
+        assert_eq!(seq.to_string(), "a한b");
+    }
+
+    #[test]
+    fn test_words() {
+        assert_eq!("world".to_string().similarity(&"worl".into()), Some(0.8));
+
+        let inputs = [
+            "Hello  world, again",
+            "Hello  worl, again",
+            "Hello  world, again",
+        ];
+        let combiner = StringCombiner::default();
+
+        let output = combiner.concat_words(inputs);
+        assert_eq!(output.as_deref(), Some("Hello  world, again"));
+    }
+
+    #[test]
+    fn test_hierarchical() {
+        let inputs = [
+            "the quick brown fox jumps",
+            "the quikc brwn fox jumps over",
+            "quick brown fox jumps over the lazy dog",
+        ];
+        let combiner = StringCombiner::default();
+
+        let output = combiner.concat_hierarchical(inputs);
+        assert_eq!(
+            output.as_deref(),
+            Some("the quick brown fox jumps over the lazy dog"),
+        );
+
+        // The character realignments are counted, and skipped over the budget
+        let inputs = ["hello quick world", "hello quikc world"];
+        let combined = combiner.combine_hierarchical(inputs).unwrap();
+        assert_eq!(50, combined.report.reduce.num_cells);
+        let combiner = StringCombiner {
+            budget: Budget {
+                max_cells: Some(30),
+                ..Budget::unlimited()
+            },
+            ..Default::default()
+        };
+        let combined = combiner.combine_hierarchical(inputs).unwrap();
+        assert_eq!(Some("hello quick world"), combined.value.as_deref());
+        assert_eq!(25, combined.report.reduce.num_cells);
+    }
+
+    #[test]
+    fn test_normalized() {
+        let inputs = [
+            "Hello, World",
+            "hello world!",
+            "\u{ff28}\u{ff45}llo world",
+            "cafe\u{301} au lait",
+            "caf\u{e9} au lait",
+        ];
+        let combiner = StringCombiner::default();
+
+        let output = combiner.concat_normalized(&inputs[..3]);
+        assert_eq!(output.as_deref(), Some("Hello world"));
+
+        let output = combiner.concat_normalized(&inputs[3..]);
+        assert_eq!(output.as_deref(), Some("cafe\u{301} au lait"));
+    }
+
+    #[test]
+    fn test_normalized_spaces() {
+        let inputs = ["뭐 하면 돼요", "뭐하면 돼요", "뭐 하면돼요"];
+        let mut combiner = StringCombiner::default();
+
+        let output = combiner.combine_normalized(inputs).unwrap();
+        assert_eq!(output.value.as_deref(), Some("뭐 하면돼요"));
+
+        combiner.normalization.fold_spaces = true;
+        let output = combiner.combine_normalized(inputs).unwrap();
+        assert_eq!(output.value.as_deref(), Some("뭐 하면 돼요"));
+
+        // The spacing is voted apart from the punctuation
+        let inputs = ["a, b", "a. b", "a! b", "ab", "ab"];
+        let output = combiner.concat_normalized(inputs);
+        assert_eq!(output.as_deref(), Some("a b"));
+
+        // The text which is stripped as a whole is kept
+        assert_eq!(combiner.concat_normalized(["?!"]).as_deref(), Some("?!"));
+    }
+
+    #[test]
+    #[should_panic(expected = "token_similarity")]
+    fn test_token_similarity_plain() {
+        let combiner = StringCombiner {
+            token_similarity: true,
+            ..Default::default()
+        };
+        combiner.concat_strings(["Hello".chars()]);
+    }
+
+    #[test]
+    fn test_token_similarity_hangul() {
+        assert_eq!('락'.similarity(&'라'), Some(0.8));
+        assert!('절'.similarity(&'찰') > '절'.similarity(&'밥'));
+        assert_eq!('a'.similarity(&'b'), None);
+
+        let combiner = StringCombiner {
+            token_similarity: true,
+            ..Default::default()
+        };
+        let scoring = combiner.token_scoring::<char>();
+        let score = |a, b| (scoring.match_fn)(&AlignedToken::new(a), &AlignedToken::new(b));
+        assert_eq!(score('락', '락'), 2);
+        assert_eq!(score('락', '라'), 1);
+        assert_eq!(score('가', 'a'), -3);
+
+        let inputs = ["신라 시절에", "신락 시찰에", "신라 시절에"];
+        let output = combiner.concat_tokens(inputs.iter().map(|s| s.chars()));
+        assert_eq!(output.as_deref(), Some("신라 시절에"));
+    }
+
+    #[test]
+    fn test_plain_tokens() {
+        #[derive(Clone, Debug, PartialEq, Eq)]
+        struct Id(u16);
+
+        impl TokenSeparator for Id {}
+
+        let segment = |t0, t1, ids: &[u16]| Segment {
+            key: key(t0, t1),
+            value: ids.iter().copied().map(Id).collect::<Vec<_>>(),
+        };
+        let inputs = [segment(0, 1000, &[1, 2, 3]), segment(500, 1500, &[2, 3, 4])];
+
+        // Any token type can be merged with only the separators defined
+        let output = StringCombiner::default().concat_segments(inputs).unwrap();
+        assert_eq!(output.value, [1, 2, 3, 4].map(Id));
+    }
+
+    #[test]
+    fn test_segments_selected() {
+        let segment = |t0, t1, kind, text: &str| Segment {
+            key: key(t0, t1),
+            value: SegmentValue {
+                kind,
+                text: text.into(),
+                tokens: Vec::new(),
+            },
+        };
+        let normal = || SegmentKind::Normal {
+            last_index: 0,
+            last_offset: None,
+            total_period: Duration::ZERO,
+        };
+        let combiner = StringCombiner::default();
+        let concat = |kinds: [SegmentKind; 2]| {
+            let [a, b] = kinds;
+            let inputs = [
+                segment(0, 1000, a, "hello wrlds, bye"),
+                segment(500, 1500, b, "hello world, by"),
+            ];
+            let output = combiner
+                .concat_segment_values_by(inputs, |value| value.text.chars().collect::<Vec<_>>());
+            output.map(|output| output.value.into_iter().collect::<String>())
+        };
+
+        assert_eq!(
+            concat([normal(), normal()]).as_deref(),
+            Some("hello wrlds, by")
+        );
+        assert_eq!(
+            concat([normal(), SegmentKind::Selected]).as_deref(),
+            Some("hello world, by"),
+        );
+        assert_eq!(
+            concat([SegmentKind::Selected, normal()]).as_deref(),
+            Some("hello wrlds, bye"),
+        );
+
+        // The whole run inserted after an anchored token is dropped
+        let inputs = [
+            segment(0, 1000, normal(), "hello big world"),
+            segment(500, 1500, SegmentKind::Selected, "hello world"),
+        ];
+        let output = combiner
+            .concat_segment_values_by(inputs, |value| value.text.chars().collect::<Vec<_>>())
+            .unwrap();
+        assert_eq!(output.value.into_iter().collect::<String>(), "hello world");
+    }
+
+    #[test]
+    fn test_reorder_segments() {
+        let segment = |t0, t1, last_index, text: &str| Segment {
+            key: key(t0, t1),
+            value: SegmentValue {
+                kind: SegmentKind::Normal {
+                    last_index,
+                    last_offset: None,
+                    // NOTE: the stream restarts at 5000
+                    total_period: Duration::from_millis(if t0 < 5000 { t1 } else { t1 - 5000 }),
+                },
+                text: text.into(),
+                tokens: Vec::new(),
+            },
+        };
+        let inputs = [
+            segment(0, 1000, 0, "one two"),
+            segment(1400, 2500, 2, "three four"),
+            segment(500, 1500, 1, "two three"),
+            segment(500, 1500, 1, "two three"),
+            segment(2500, 3500, 5, "five"),
+            segment(5000, 6000, 0, "again"),
+            segment(3500, 4500, 6, "six"),
+        ];
+        let combiner = StringCombiner {
+            reorder_segments: true,
+            ..Default::default()
+        };
+
+        let output = combiner
+            .combine_segment_values_by(inputs.clone(), |value| {
+                value.text.chars().collect::<Vec<_>>()
+            })
+            .unwrap();
+        assert_eq!(
+            output.report.sequence,
+            [
+                SequenceEvent::Reordered {
+                    index: 2,
+                    last_index: 1,
+                },
+                SequenceEvent::Reordered {
+                    index: 3,
+                    last_index: 1,
+                },
+                SequenceEvent::Restarted {
+                    index: 5,
+                    last_index: 0,
+                },
+                SequenceEvent::Duplicated {
+                    index: 3,
+                    last_index: 1,
+                },
+                SequenceEvent::Missing {
+                    after_index: 2,
+                    len: 2,
+                },
+            ],
+        );
+        let output: String = output.value.unwrap().value.into_iter().collect();
+        // The late segment of the first stream goes before the restarted one
+        assert_eq!(output, "one two three fourfivesixagain");
+
+        // The error reports the index of the input, not the one after the reordering
+        let combiner = StringCombiner {
+            limits: Limits {
+                max_segment_len: Some(9),
+                policy: LimitPolicy::Error,
+                ..Default::default()
+            },
+            ..combiner
+        };
+        let error = combiner
+            .combine_segment_values_by(inputs, |value| value.text.chars().collect::<Vec<_>>())
+            .unwrap_err();
+        assert_eq!(1, error.event.index);
+    }
+
+    #[test]
+    fn test_session_replace() {
+        let mut session = CombineSession::default();
+
+        let id = session.push(segment(0, 500, "hallo")).id().unwrap();
+        let id = session.replace(id, segment(0, 800, "hallo wold")).unwrap();
+        let id = session
+            .replace(id, segment(0, 1000, "hello world"))
+            .unwrap();
+        session.push(segment(800, 2000, "world again"));
+        assert_eq!(session.len(), 2);
+        assert!(session.get(SegmentId(0)).is_none());
+        assert_eq!(session.get(id).unwrap().value.len(), 11);
+
+        let output: String = session.concat().unwrap().value.into_iter().collect();
+        assert_eq!(output, "hello world again");
+    }
+
+    #[test]
+    fn test_session_remove() {
+        let mut session = CombineSession::default();
+
+        session.push(segment(0, 1000, "good morning"));
+        let id = session
+            .push(segment(500, 1500, "morning noise from elsewhere"))
+            .id()
+            .unwrap();
+        session.push(segment(900, 2000, "morning everyone"));
+        session.push(segment(900, 2000, "morning everyone"));
+
+        let removed = session.remove(id).unwrap();
+        assert_eq!(removed.key.t0, Duration::from_millis(500));
+        assert!(session.remove(id).is_none());
+
+        let output: String = session.concat().unwrap().value.into_iter().collect();
+        assert_eq!(output, "good morning everyone");
+    }
+
+    #[test]
+    fn test_session_reorder() {
+        let mut session = CombineSession::default().with_reorder(Reorder {
+            lateness: Duration::from_secs(1),
+        });
+
+        assert!(matches!(
+            session.insert(segment(1000, 2000, "one two")),
+            Insertion::InOrder(_),
+        ));
+        assert!(matches!(
+            session.insert(segment(2800, 3800, "three four")),
+            Insertion::InOrder(_),
+        ));
+        assert!(matches!(
+            session.insert(segment(1500, 3000, "two three")),
+            Insertion::Reordered(_),
+        ));
+        assert_eq!(
+            session.insert(segment(0, 1200, "zero one")),
+            Insertion::Dropped
+        );
+
+        let output: String = session.concat().unwrap().value.into_iter().collect();
+        assert_eq!(output, "one two three four");
+
+        // The late segments are dropped when pushed as well
+        assert_eq!(
+            session.push(segment(0, 1200, "zero one")),
+            Insertion::Dropped
+        );
+
+        // The whole session is replayed, so a wider window merges them as if in order
+        let mut session = session.with_reorder(Reorder {
+            lateness: Duration::from_secs(3),
+        });
+        assert!(matches!(
+            session.insert(segment(0, 1200, "zero one")),
+            Insertion::Reordered(_),
+        ));
+        let output: String = session.concat().unwrap().value.into_iter().collect();
+        assert_eq!(output, "zero one two three four");
+    }
+
+    #[test]
+    fn test_segments_overlap() {
+        let concat = |combiner: &StringCombiner, inputs| {
+            let output = combiner.concat_token_segments(inputs).unwrap();
+            output.value.into_iter().collect::<String>()
+        };
+        let inputs = || {
+            [
+                segment(0, 1000, "hello world"),
+                segment(980, 2000, "world again"),
+            ]
+        };
+
+        let mut combiner = StringCombiner::default();
+        assert_eq!(concat(&combiner, inputs()), "hello world again");
+
+        combiner.overlap.min_ratio = 0.1;
+        assert_eq!(concat(&combiner, inputs()), "hello worldworld again");
+
+        combiner.overlap.slack = Duration::from_millis(100);
+        assert_eq!(concat(&combiner, inputs()), "hello world again");
+
+        // The earlier segments are overlapped only without a minimum ratio
+        let ms = Duration::from_millis;
+        let (a, b) = ((ms(1000), ms(2000)), (ms(0), ms(500)));
+        assert!(SegmentOverlap::default().is_overlapped(a, b));
+        assert!(!combiner.overlap.is_overlapped(a, b));
+    }
+
+    #[test]
+    fn test_segments_token_spans() {
+        let inputs = [
+            Segment {
+                key: SegmentKey {
+                    t0: Duration::ZERO,
+                    t1: Duration::from_millis(1500),
+                },
+                value: vec![token(0, 0, 500), token(1, 500, 1000)],
+            },
+            Segment {
+                key: SegmentKey {
+                    t0: Duration::from_millis(500),
+                    t1: Duration::from_millis(2000),
+                },
+                value: vec![token(1, 1300, 1600), token(2, 1600, 2000)],
+            },
+        ];
+        let concat = |combiner: &StringCombiner| {
+            let output = combiner.concat_token_segments(inputs.clone()).unwrap();
+            output
+                .value
+                .iter()
+                .map(|token| token.id)
+                .collect::<Vec<_>>()
+        };
+
+        let mut combiner = StringCombiner::default();
+        assert_eq!(concat(&combiner), [0, 1, 2]);
+
+        // The padded keys overlap, but the tokens do not
+        combiner.overlap.token_spans = true;
+        assert_eq!(concat(&combiner), [0, 1, 1, 2]);
+    }
+
+    #[test]
+    fn test_join_separator_votes() {
+        let a: AlignedSequence<_> = "hello".chars().collect();
+        let b: AlignedSequence<_> = "world".chars().collect();
+
+        // The separators add no votes of their own
+        let joined = a.join(&b, Some(&[' ']));
+        assert_eq!("hello world", joined.to_string());
+        assert_eq!(0, joined.value[5].count);
+        assert_eq!(10, joined.total_matched());
+    }
+
+    #[test]
+    fn test_gap_policy() {
+        let inputs = [
+            segment(0, 1000, "hello"),
+            segment(1200, 2000, "world"),
+            segment(3500, 4000, "again"),
+            segment(7000, 8000, "bye"),
+        ];
+        let combiner = StringCombiner {
+            gap_policy: GapPolicy {
+                separators: vec![
+                    (Duration::ZERO, Separator::Space),
+                    (Duration::from_secs(1), Separator::Newline),
+                    (Duration::from_secs(2), Separator::Silence),
+                ],
+            },
+            ..Default::default()
+        };
+
+        let output = combiner.concat_token_segments(inputs.clone()).unwrap();
+        let output: String = output.value.into_iter().collect();
+        assert_eq!(output, "hello world\nagain[silence 3.0s]bye");
+
+        // The plain entry points follow the same policy
+        let output = combiner.concat_segments(inputs).unwrap();
+        let output: String = output.value.into_iter().collect();
+        assert_eq!(output, "hello world\nagain[silence 3.0s]bye");
+    }
+
+    #[test]
+    fn test_gap_policy_tokens() {
+        let vocab = byte_vocab();
+
+        let inputs = [
+            byte_segment(0, 1000, 0, "hello"),
+            byte_segment(1500, 2000, 1, "world"),
+        ];
+        let combiner = StringCombiner {
+            gap_policy: GapPolicy {
+                separators: vec![(Duration::ZERO, Separator::Space)],
+            },
+            ..Default::default()
+        };
+
+        // The separator is tokenized and timed to the gap
+        let output = combiner.concat_segment_values(inputs, &vocab).unwrap();
+        assert_eq!(output.text, "hello world");
+        let separator = output.tokens[5];
+        assert_eq!(separator.id, i32::from(b' '));
+        assert_eq!(separator.t0, Duration::from_millis(1000));
+        assert_eq!(separator.t1, Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn test_ticks_saturate() {
+        let max = Ticks::<48_000>(u64::MAX);
+        assert_eq!(max.advance(Duration::from_secs(1)), max);
+        assert_eq!(Ticks::<48_000>::from_duration(Duration::MAX), max);
+        assert_eq!(u64::MAX.advance(Duration::from_millis(1)), u64::MAX);
+        assert_eq!(1000u64.elapsed(&1500), Duration::from_millis(500));
+        assert_eq!(u64::from_timestamp(Duration::from_secs(2)), Some(2000));
+    }
+
+    #[test]
+    fn test_segments_ticks() {
+        let segment = |t0, t1, text: &str| Segment {
+            key: SegmentKey {
+                t0: Ticks::<16_000>(t0),
+                t1: Ticks::<16_000>(t1),
+            },
+            value: text.chars().collect::<Vec<_>>(),
+        };
+
+        let combiner = StringCombiner::default().with_time_key();
+        let output = combiner
+            .concat_segments([
+                segment(0, 16_000, "hello world"),
+                segment(15_680, 32_000, "world again"),
+            ])
+            .unwrap();
+        assert_eq!(output.key.t1, Ticks(32_000));
+        assert_eq!(
+            output.value.into_iter().collect::<String>(),
+            "hello world again"
+        );
+
+        let mut session = CombineSession::new(combiner);
+        session.insert(segment(48_000, 64_000, "three four"));
+        assert_eq!(
+            session.insert(segment(0, 16_000, "zero one")),
+            Insertion::Dropped
+        );
+        assert!(matches!(
+            session.insert(segment(32_000, 48_000, "two three")),
+            Insertion::Reordered(_),
+        ));
+    }
+
+    #[test]
+    fn test_segments_split() {
+        let inputs = [
+            segment(0, 1000, "Hi there. How"),
+            segment(600, 2000, "How are you?"),
+        ];
+        let combiner = StringCombiner::default();
+
+        let split = SegmentSplit {
+            at_edges: false,
+            ..Default::default()
+        };
+        let output = combiner.concat_segments_split(inputs, &split).unwrap();
+        let texts: Vec<String> = output.iter().map(|s| s.value.iter().collect()).collect();
+        assert_eq!(texts, ["Hi there.", "How are you?"]);
+        assert_eq!(output[0].key.t0, Duration::ZERO);
+        assert_eq!(output[1].key.t1, Duration::from_millis(2000));
+
+        // The untimed tokens are split at the interpolated edges
+        let inputs = [
+            segment(0, 1000, "Hello world "),
+            segment(1000, 2000, "Good morning"),
+        ];
+        let split = SegmentSplit {
+            at_punctuation: false,
+            ..Default::default()
+        };
+        let output = combiner.concat_segments_split(inputs, &split).unwrap();
+        let texts: Vec<String> = output.iter().map(|s| s.value.iter().collect()).collect();
+        assert_eq!(texts, ["Hello world", "Good morning"]);
+        assert_eq!(output[1].key.t1, Duration::from_millis(2000));
+
+        let tokens = vec![token(0, 0, 100), token(1, 100, 200), token(2, 900, 1000)];
+        let output = SegmentSplit::default().split(
+            Segment {
+                key: SegmentKey {
+                    t0: Duration::ZERO,
+                    t1: Duration::from_millis(1000),
+                },
+                value: tokens,
+            },
+            &[],
+        );
+        assert_eq!(output.len(), 2);
+        assert_eq!(output[1].key.t0, Duration::from_millis(900));
+    }
+
+    #[test]
+    fn test_segment_tokens() {
+        let vocab = Vocabulary::from_tiktoken(
+            [
+                "YQ== 0", "Yg== 1", "Yw== 2", "IA== 3", "YWI= 4", "YmM= 5", "YWJj 6",
+            ]
+            .join("\n")
+            .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(vocab.len(), 7);
+
+        let segment = |t0, t1, tokens: Vec<TokenData>| Segment {
+            key: key(t0, t1),
+            value: SegmentValue {
+                kind: SegmentKind::Selected,
+                text: vocab.decode_text(&tokens),
+                tokens,
+            },
+        };
+        let inputs = [
+            segment(0, 1000, vec![token(4, 0, 400), token(2, 400, 600)]),
+            segment(
+                300,
+                1300,
+                vec![token(0, 300, 400), token(5, 400, 600), token(3, 600, 700)],
+            ),
+        ];
+        let combiner = StringCombiner::default();
+
+        let output = combiner.concat_segment_tokens(inputs, &vocab).unwrap();
+        assert_eq!(
+            output
+                .value
+                .iter()
+                .map(|token| token.id)
+                .collect::<Vec<_>>(),
+            [6, 3],
+        );
+        assert_eq!(output.value[0].t0, Duration::from_millis(0));
+        assert_eq!(vocab.decode_text(&output.value), "abc ");
+    }
+
+    #[test]
+    fn test_vocab_merges() {
+        let json = r#"{
+            "model": {
+                "vocab": {"a": 0, "b": 1, "c": 2, "bc": 3, "ab": 5},
+                "merges": ["a b", ["b", "c"]]
+            }
+        }"#;
+        let vocab = Vocabulary::from_tokenizer_json(json.as_bytes()).unwrap();
+        let bytes = |text: &str| {
+            let bytes: Vec<_> = text
+                .bytes()
+                .map(|b| (b, Duration::ZERO, Duration::ZERO))
+                .collect();
+            TimedByte::split(&bytes)
+        };
+
+        // The merges are ranked by their order, not by the token ids
+        let ids = |tokens: Vec<TokenData>| tokens.iter().map(|t| t.id).collect::<Vec<_>>();
+        assert_eq!(ids(vocab.encode(&bytes("abc")).unwrap()), [5, 2]);
+
+        // The bytes which are not in the vocabulary are rejected
+        let error = vocab.encode(&bytes("abd")).err();
+        assert_eq!(Some(EncodeError { offset: 2, len: 1 }), error);
+
+        // The duplicated bytes are encoded into the lowest id
+        let vocab: Vocabulary = [(7, b"a".to_vec()), (0, b"a".to_vec())]
+            .into_iter()
+            .collect();
+        assert_eq!(ids(vocab.encode(&bytes("a")).unwrap()), [0]);
+    }
+
+    #[test]
+    fn test_segment_values() {
+        let vocab: Vocabulary = [(0, b"a".to_vec()), (1, b"b".to_vec()), (2, b"ab".to_vec())]
+            .into_iter()
+            .collect();
+
+        let segment = |t0, t1, last_index, tokens: Vec<i32>| Segment {
+            key: key(t0, t1),
+            value: SegmentValue {
+                kind: SegmentKind::Normal {
+                    last_index,
+                    last_offset: Some(Duration::from_millis(t1)),
+                    total_period: Duration::from_millis(t1),
+                },
+                text: "stale".into(),
+                tokens: tokens
+                    .into_iter()
+                    .map(|id| TokenData {
+                        id,
+                        t0: Duration::from_millis(t0),
+                        t1: Duration::from_millis(t0 + 100),
+                    })
+                    .collect(),
+            },
+        };
+        let inputs = [
+            segment(0, 1000, 0, vec![2, 0]),
+            segment(500, 1500, 1, vec![0, 1, 0]),
+        ];
+        let combiner = StringCombiner::default();
+
+        let output = combiner.concat_segment_values(inputs, &vocab).unwrap();
+        assert_eq!(output.text, vocab.decode_text(&output.tokens));
+        assert_eq!(output.text, "aba");
+        assert!(matches!(
+            output.kind,
+            SegmentKind::Normal { last_index: 1, last_offset: Some(offset), .. }
+                if offset == Duration::from_millis(1500),
+        ));
+    }
+
+    #[test]
+    fn test_segment_values_utf8() {
+        let vocab = byte_vocab();
+
+        let inputs = [
+            byte_segment(0, 1000, 0, "안 다a"),
+            byte_segment(100, 1100, 1, "녕 다안"),
+            byte_segment(200, 1200, 2, "녕 각a"),
+        ];
+        let combiner = StringCombiner::default();
+
+        // The bytes of the different characters are never merged
+        let output = combiner.concat_segment_values(inputs, &vocab).unwrap();
+        assert_eq!(output.text, vocab.decode_text(&output.tokens));
+        assert_eq!(output.text, "안 다안");
+    }
+
+    #[test]
+    fn test_phrase_filter() {
+        let segment = |t0, t1, text: &str, tokens| Segment {
+            key: key(t0, t1),
+            value: SegmentValue {
+                kind: SegmentKind::Selected,
+                text: text.into(),
+                tokens,
+            },
+        };
+        let inputs = vec![
+            segment(
+                0,
+                3000,
+                " Hello world Thanks for watching",
+                vec![token(1, 0, 500), token(2, 500, 1000), token(9, 2600, 2900)],
+            ),
+            // a genuine phrase in the middle of the speech
+            segment(
+                3000,
+                6000,
+                " Hello Thanks for watching world",
+                vec![
+                    token(1, 3000, 3500),
+                    token(9, 3500, 4000),
+                    token(2, 4000, 4500),
+                ],
+            ),
+        ];
+        let combiner = StringCombiner {
+            phrase_filter: Some(PhraseFilter {
+                phrases: vec![Phrase {
+                    text: " Thanks for watching".into(),
+                    tokens: vec![9],
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let combined = combiner
+            .combine_segment_values_by(inputs, |value| value.tokens)
+            .unwrap();
+        let ids: Vec<_> = combined.value.unwrap().value.iter().map(|t| t.id).collect();
+        assert_eq!(vec![1, 2, 1, 9, 2], ids);
+        assert_eq!(1, combined.report.phrases.len());
+        assert_eq!(
+            RemovalReason::SegmentEdge,
+            combined.report.phrases[0].reason
+        );
+    }
+
+    #[test]
+    fn test_phrase_filter_ticks() {
+        let inputs = [Segment {
+            key: SegmentKey {
+                t0: Ticks::<1000>(0),
+                t1: Ticks::<1000>(3000),
+            },
+            value: SegmentValue {
+                kind: SegmentKind::Selected,
+                text: " Hello world Thanks for watching".into(),
+                tokens: vec![token(1, 0, 500), token(2, 500, 1000), token(9, 2600, 2900)],
+            },
+        }];
+        let combiner = StringCombiner {
+            phrase_filter: Some(PhraseFilter {
+                phrases: vec![Phrase {
+                    text: " Thanks for watching".into(),
+                    tokens: vec![9],
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+        .with_time_key();
+
+        // The token timestamps are compared with the keys in ticks
+        let combined = combiner
+            .combine_segment_values_by(inputs, |value| value.tokens)
+            .unwrap();
+        let output = combined.value.unwrap();
+        let ids: Vec<_> = output.value.iter().map(|t| t.id).collect();
+        assert_eq!(vec![1, 2], ids);
+        assert_eq!(Ticks(3000), output.key.t1);
+        assert_eq!(
+            RemovalReason::SegmentEdge,
+            combined.report.phrases[0].reason
+        );
+    }
+
+    #[test]
+    fn test_phrase_filter_unmatched_text() {
+        let mut segment = Segment {
+            key: SegmentKey {
+                t0: Duration::ZERO,
+                t1: Duration::from_millis(3000),
+            },
+            value: SegmentValue {
+                kind: SegmentKind::Selected,
+                text: " Hello world Thank you".into(),
+                tokens: vec![token(1, 0, 500), token(2, 500, 1000), token(9, 2600, 2900)],
+            },
+        };
+        let phrase_filter = PhraseFilter {
+            phrases: vec![Phrase {
+                text: " Thanks for watching".into(),
+                tokens: vec![9],
+            }],
+            ..Default::default()
+        };
+
+        // The tokens are kept along with the text, which does not contain the phrase
+        let mut unmatched = Vec::new();
+        let removed = phrase_filter.filter(0, &mut segment, None, &mut unmatched);
+        assert!(removed.is_empty());
+        assert_eq!(3, segment.value.tokens.len());
+        assert_eq!(" Hello world Thank you", segment.value.text);
+        assert_eq!(1, unmatched.len());
+        assert_eq!(Duration::from_millis(2600), unmatched[0].t0);
+    }
+
+    #[test]
+    fn test_phrase_filter_text_span() {
+        let vocab: Vocabulary = [
+            (0, b" Thanks for watching".to_vec()),
+            (1, b" Thanks".to_vec()),
+            (2, b" for".to_vec()),
+            (3, b" watching".to_vec()),
+            (4, b" ok".to_vec()),
+        ]
+        .into_iter()
+        .collect();
+        let segment = || Segment {
+            key: key(0, 3000),
+            value: SegmentValue {
+                kind: SegmentKind::Selected,
+                text: " Thanks for watching ok Thanks for watching".into(),
+                tokens: vec![
+                    token(1, 500, 1000),
+                    token(2, 1000, 1200),
+                    token(3, 1200, 1500),
+                    token(4, 1500, 2000),
+                    token(0, 2600, 2900),
+                ],
+            },
+        };
+        let phrase_filter = PhraseFilter {
+            phrases: vec![Phrase {
+                text: " Thanks for watching".into(),
+                tokens: vec![0],
+            }],
+            ..Default::default()
+        };
+
+        // The text spelled by the other tokens is kept
+        let mut unmatched = Vec::new();
+        let mut output = segment();
+        let removed = phrase_filter.filter(0, &mut output, Some(&vocab), &mut unmatched);
+        assert_eq!(1, removed.len());
+        assert_eq!(" Thanks for watching ok", output.value.text);
+        assert_eq!(4, output.value.tokens.len());
+
+        // Without a vocabulary, the text is ambiguous
+        let mut output = segment();
+        let removed = phrase_filter.filter(0, &mut output, None, &mut unmatched);
+        assert!(removed.is_empty());
+        assert_eq!(5, output.value.tokens.len());
+        assert_eq!(1, unmatched.len());
     }
 }
@@ -0,0 +1,82 @@
+use std::{error::Error, fmt};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Limits {
+    // NOTE: the number of DP cells of a single pairwise alignment
+    pub max_cells: Option<usize>,
+    pub max_segment_len: Option<usize>,
+    pub max_total_len: Option<usize>,
+    pub policy: LimitPolicy,
+}
+
+impl Limits {
+    #[inline]
+    pub const fn unlimited() -> Self {
+        Self {
+            max_cells: None,
+            max_segment_len: None,
+            max_total_len: None,
+            policy: LimitPolicy::Truncate,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LimitPolicy {
+    #[default]
+    Truncate,
+    Skip,
+    Error,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LimitKind {
+    Cells,
+    SegmentLen,
+    TotalLen,
+}
+
+impl fmt::Display for LimitKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cells => "DP cells".fmt(f),
+            Self::SegmentLen => "segment length".fmt(f),
+            Self::TotalLen => "total length".fmt(f),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct LimitEvent {
+    // NOTE: the position of the offending input
+    pub index: usize,
+    pub kind: LimitKind,
+    pub len: usize,
+    pub limit: usize,
+    pub policy: LimitPolicy,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct LimitError {
+    pub event: LimitEvent,
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let LimitEvent {
+            index,
+            kind,
+            len,
+            limit,
+            policy: _,
+        } = &self.event;
+        write!(
+            f,
+            "input #{index} exceeds the {kind} limit: {len} > {limit}"
+        )
+    }
+}
+
+impl Error for LimitError {}
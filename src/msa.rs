@@ -3,15 +3,23 @@ use std::{
     cmp::min,
     fmt,
     marker::PhantomData,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+    time::{Duration, Instant},
 };
 
 pub use bio::alignment::pairwise::Scoring;
 use bio::alignment::{pairwise::Aligner, Alignment, AlignmentMode, AlignmentOperation};
 #[cfg(feature = "rayon")]
-use rayon::current_num_threads;
+use rayon::{current_num_threads, prelude::*, ThreadPool};
 #[cfg(feature = "rayon")]
 use rayon_cond::CondIterator;
 use serde::{Deserialize, Serialize};
+use thread_local::ThreadLocal;
+
+use crate::limit::{LimitError, LimitEvent, LimitKind, LimitPolicy, Limits};
 
 pub trait MultipleSequenceAlignment<I, T, V>
 where
@@ -19,6 +27,7 @@ where
 {
     type Output;
 
+    #[inline]
     fn reduce_all<F, Iter>(
         &self,
         scoring: Scoring<F, T>,
@@ -26,11 +35,116 @@ where
         inputs: Iter,
     ) -> Option<Self::Output>
     where
-        F: Sync + Clone + Fn(&T, &T) -> i32,
+        F: Send + Sync + Clone + Fn(&T, &T) -> i32,
+        I: AsRef<[T]>,
+        Iter: IntoIterator,
+        Iter::Item: AsRef<I>,
+        T: Send + Clone + Eq,
+    {
+        let pool = AlignerPool::new(scoring);
+        self.reduce_all_with_pool(&pool, visitor, inputs)
+    }
+
+    fn reduce_all_with_pool<F, Iter>(
+        &self,
+        pool: &AlignerPool<F, T>,
+        visitor: V,
+        inputs: Iter,
+    ) -> Option<Self::Output>
+    where
+        F: Send + Sync + Clone + Fn(&T, &T) -> i32,
         I: AsRef<[T]>,
         Iter: IntoIterator,
         Iter::Item: AsRef<I>,
-        T: Clone + Eq;
+        T: Send + Clone + Eq;
+}
+
+// NOTE: each thread owns its workspace, so the parallel candidates never contend for a lock
+pub struct AlignerPool<F, T>
+where
+    F: Send,
+    T: Send,
+{
+    aligners: ThreadLocal<Mutex<Option<Aligner<F, T>>>>,
+    scoring: Scoring<F, T>,
+}
+
+impl<F, T> AlignerPool<F, T>
+where
+    F: Send + Clone + Fn(&T, &T) -> i32,
+    T: Send + Clone,
+{
+    #[inline]
+    pub const fn new(scoring: Scoring<F, T>) -> Self {
+        Self {
+            aligners: ThreadLocal::new(),
+            scoring,
+        }
+    }
+
+    #[inline]
+    pub fn scoring(&self) -> &Scoring<F, T> {
+        &self.scoring
+    }
+
+    pub fn len(&self) -> usize {
+        self.aligners
+            .iter()
+            .filter(|slot| Self::lock(slot).is_some())
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.aligners
+            .iter()
+            .for_each(|slot| *Self::lock(slot) = None)
+    }
+
+    pub fn with<R>(&self, x: &[T], y: &[T], f: impl FnOnce(&mut Aligner<F, T>) -> R) -> R {
+        // NOTE: the workspace is only ever grown; a re-entrant call on the same thread gets a fresh one
+        let slot = self.aligners.get_or_default();
+        let mut aligner = Self::lock(slot).take().unwrap_or_else(|| {
+            Aligner::with_capacity_and_scoring(x.len(), y.len(), self.scoring.clone())
+        });
+        let output = f(&mut aligner);
+        *Self::lock(slot) = Some(aligner);
+        output
+    }
+
+    #[inline]
+    fn lock(slot: &Mutex<Option<Aligner<F, T>>>) -> MutexGuard<'_, Option<Aligner<F, T>>> {
+        // NOTE: only `len` and `clear` ever touch the slot of another thread
+        slot.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<F, T> Clone for AlignerPool<F, T>
+where
+    F: Send + Clone,
+    T: Send + Clone,
+{
+    // NOTE: the workspaces are not shared, so a clone starts empty
+    #[inline]
+    fn clone(&self) -> Self {
+        Self {
+            aligners: ThreadLocal::new(),
+            scoring: self.scoring.clone(),
+        }
+    }
+}
+
+impl<F, T> fmt::Debug for AlignerPool<F, T>
+where
+    F: Send,
+    T: Send,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AlignerPool").finish_non_exhaustive()
+    }
 }
 
 #[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
@@ -44,18 +158,18 @@ where
 {
     type Output = V::Output;
 
-    fn reduce_all<F, Iter>(
+    fn reduce_all_with_pool<F, Iter>(
         &self,
-        scoring: Scoring<F, T>,
+        pool: &AlignerPool<F, T>,
         visitor: V,
         inputs: Iter,
     ) -> Option<Self::Output>
     where
-        F: Clone + Fn(&T, &T) -> i32,
+        F: Send + Sync + Clone + Fn(&T, &T) -> i32,
         I: AsRef<[T]>,
         Iter: IntoIterator,
         Iter::Item: AsRef<I>,
-        T: Clone + Eq,
+        T: Send + Clone + Eq,
     {
         let mut inputs = inputs.into_iter();
         let first = inputs.next()?;
@@ -63,15 +177,129 @@ where
 
         for y in inputs {
             let y = y.as_ref().as_ref();
-            let mut aligner = Aligner::with_capacity_and_scoring(x.len(), y.len(), scoring.clone());
-            let alignment = aligner.semiglobal(&x, y);
+            let alignment = pool.with(&x, y, |aligner| aligner.semiglobal(&x, y));
             x = Cow::Owned(alignment.reduce(visitor.clone(), &x, y))
         }
         Some(x.into_owned())
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug, Default)]
+pub struct Parallelism {
+    pub granularity: ParallelGranularity,
+    // NOTE: `None` falls back to 5 candidates per thread of the active pool
+    pub min_parallel_len: Option<usize>,
+    #[cfg(feature = "rayon")]
+    pub thread_pool: Option<Arc<ThreadPool>>,
+}
+
+impl Parallelism {
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            granularity: ParallelGranularity::Candidate,
+            min_parallel_len: None,
+            #[cfg(feature = "rayon")]
+            thread_pool: None,
+        }
+    }
+
+    #[inline]
+    pub const fn sequential() -> Self {
+        Self {
+            granularity: ParallelGranularity::Sequential,
+            min_parallel_len: None,
+            #[cfg(feature = "rayon")]
+            thread_pool: None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ParallelGranularity {
+    // Align every candidate of the table as a separate task
+    #[default]
+    Candidate,
+    // Split the table into one contiguous partition per thread
+    Partition,
+    Sequential,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Budget {
+    pub lookback: usize,
+    pub max_cells: Option<usize>,
+    pub time_limit: Option<Duration>,
+}
+
+impl Default for Budget {
+    #[inline]
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
+
+impl Budget {
+    #[inline]
+    pub const fn unlimited() -> Self {
+        Self {
+            lookback: 4,
+            max_cells: None,
+            time_limit: None,
+        }
+    }
+
+    fn degradation(&self, elapsed: Duration, num_cells: usize) -> Option<Degradation> {
+        let usage = [
+            self.time_limit
+                .map(|limit| elapsed.as_secs_f64() / limit.as_secs_f64()),
+            self.max_cells.map(|limit| num_cells as f64 / limit as f64),
+        ]
+        .into_iter()
+        .flatten()
+        .fold(0.0, f64::max);
+
+        // NOTE: whenever the budget is exhausted once more, fall back to a cheaper strategy
+        if usage < 1.0 {
+            None
+        } else if usage < 2.0 {
+            Some(Degradation::Lookback)
+        } else if usage < 4.0 {
+            Some(Degradation::ExactOverlap)
+        } else {
+            Some(Degradation::Concat)
+        }
+    }
+}
+
+// NOTE: variants are ordered from the most expensive strategy
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Degradation {
+    // Align only with the most recent states
+    Lookback,
+    // Join the most recent states on the longest exactly matching overlap
+    ExactOverlap,
+    // Concatenate to the latest state
+    Concat,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReduceReport {
+    pub degradation: Option<Degradation>,
+    pub elapsed: Duration,
+    pub limits: Vec<LimitEvent>,
+    pub num_cells: usize,
+    pub num_degraded: usize,
+}
+
+impl ReduceReport {
+    #[inline]
+    pub fn is_degraded(&self) -> bool {
+        self.degradation.is_some()
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct GreedyMultipleSequenceAligner<Fm, Fs, I, S>
 where
     Fm: Sync + Fn(&I, &I) -> SequenceMatch<I>,
@@ -79,7 +307,12 @@ where
     S: Ord,
 {
     pub _item: PhantomData<(I, S)>,
+    pub budget: Budget,
+    pub limits: Limits,
     pub match_fn: Fm,
+    // NOTE: the DP cells counter shared with the visitors which align on their own
+    pub num_cells: Option<Arc<AtomicUsize>>,
+    pub parallelism: Parallelism,
     pub score_fn: Fs,
 }
 
@@ -93,10 +326,32 @@ where
     pub const fn new(match_fn: Fm, score_fn: Fs) -> Self {
         Self {
             _item: PhantomData,
+            budget: Budget::unlimited(),
+            limits: Limits::unlimited(),
             match_fn,
+            num_cells: None,
+            parallelism: Parallelism::new(),
             score_fn,
         }
     }
+
+    #[inline]
+    pub fn with_budget(mut self, budget: Budget) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    #[inline]
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    #[inline]
+    pub fn with_parallelism(mut self, parallelism: Parallelism) -> Self {
+        self.parallelism = parallelism;
+        self
+    }
 }
 
 impl<Fm, Fs, I, S, T, V> MultipleSequenceAlignment<I, AlignedToken<T>, V>
@@ -112,22 +367,61 @@ where
 {
     type Output = I;
 
-    fn reduce_all<Fscore, Iter>(
+    #[inline]
+    fn reduce_all_with_pool<Fscore, Iter>(
         &self,
-        scoring: Scoring<Fscore, AlignedToken<T>>,
+        pool: &AlignerPool<Fscore, AlignedToken<T>>,
         visitor: V,
         inputs: Iter,
     ) -> Option<Self::Output>
     where
-        AlignedToken<T>: Clone + Eq,
-        Fscore: Sync + Clone + Fn(&AlignedToken<T>, &AlignedToken<T>) -> i32,
+        AlignedToken<T>: Send + Clone + Eq,
+        Fscore: Send + Sync + Clone + Fn(&AlignedToken<T>, &AlignedToken<T>) -> i32,
         I: AsRef<[AlignedToken<T>]>,
         Iter: IntoIterator,
         Iter::Item: AsRef<I>,
     {
+        let (output, _) = self.reduce_all_with_report(pool, visitor, inputs).ok()?;
+        output
+    }
+}
+
+impl<Fm, Fs, I, S> GreedyMultipleSequenceAligner<Fm, Fs, I, S>
+where
+    Fm: Sync + Fn(&I, &I) -> SequenceMatch<I>,
+    Fs: Sync + Fn(&I) -> Option<S>,
+    I: Send + Sync + Clone,
+    S: Send + Sync + Copy + Ord,
+{
+    pub fn reduce_all_with_report<Fscore, Iter, T, V>(
+        &self,
+        pool: &AlignerPool<Fscore, AlignedToken<T>>,
+        visitor: V,
+        inputs: Iter,
+    ) -> Result<(Option<I>, ReduceReport), LimitError>
+    where
+        AlignedToken<T>: Send + Clone + Eq,
+        Fscore: Send + Sync + Clone + Fn(&AlignedToken<T>, &AlignedToken<T>) -> i32,
+        I: AsRef<[AlignedToken<T>]> + GreedyMultipleSequenceAlignerItem<T>,
+        Iter: IntoIterator,
+        Iter::Item: AsRef<I>,
+        T: Send + Sync,
+        V: Send + Sync + Clone + AlignmentVisitor<AlignedToken<T>>,
+        V::Output: Into<AlignedSequence<T>>,
+    {
+        // NOTE: fields are ordered; the ranking is total so that the result does not
+        //       depend on the reduction order of the parallel iterators.
+        //       If the scores are the same, we choose the latter, and then the longer one.
+        #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+        struct Rank<S> {
+            score: Option<S>,
+            index: usize,
+            len: usize,
+        }
+
         struct State<I, S, T> {
             _item: PhantomData<T>,
-            score: Option<S>,
+            rank: Rank<S>,
             seq: I,
         }
 
@@ -143,47 +437,165 @@ where
 
         impl<I, S, T> State<I, S, T> {
             #[inline]
-            fn new(score: S, seq: I) -> Self {
+            fn new(rank: Rank<S>, seq: I) -> Self {
                 Self {
                     _item: PhantomData,
-                    score: Some(score),
+                    rank,
                     seq,
                 }
             }
 
             #[inline]
-            fn genesis(score: Option<S>, seq: &I) -> Self
+            fn genesis(rank: Rank<S>, seq: &I) -> Self
             where
                 I: Clone,
             {
                 Self {
                     _item: PhantomData,
-                    score,
+                    rank,
                     seq: seq.clone(),
                 }
             }
         }
 
-        let calculate_seq = |x: &State<I, S, T>, y: &I| -> Option<I> {
+        let own_cells = AtomicUsize::new(0);
+        let num_cells = self.num_cells.as_deref().unwrap_or(&own_cells);
+        let limit_events = Mutex::new(Vec::new());
+        let calculate_seq = |x: &State<I, S, T>, y_index, y: &I, degradation| -> Option<I> {
             match (self.match_fn)(&x.seq, y) {
                 SequenceMatch::Matched => {
-                    let mut aligner = Aligner::with_capacity_and_scoring(
-                        x.seq.as_ref().len(),
-                        y.as_ref().len(),
-                        scoring.clone(),
-                    );
-                    let alignment = aligner.local(x.seq.as_ref(), y.as_ref());
-                    let seq = alignment.reduce(visitor.clone(), x.seq.as_ref(), y.as_ref());
-                    Some(I::build(&x.seq, y, seq.into()))
+                    let (x_seq, y_seq) = (x.seq.as_ref(), y.as_ref());
+                    let seq = match degradation {
+                        None | Some(Degradation::Lookback) => {
+                            // Align only the ends facing each other if the DP table is too large
+                            let mut window = x_seq.len();
+                            let mut y_window = y_seq.len();
+                            let cells = x_seq.len() * y_seq.len();
+                            if let Some(limit) = self.limits.max_cells.filter(|&max| cells > max) {
+                                let policy = self.limits.policy;
+                                limit_events
+                                    .lock()
+                                    .unwrap_or_else(PoisonError::into_inner)
+                                    .push(LimitEvent {
+                                        index: y_index,
+                                        kind: LimitKind::Cells,
+                                        len: cells,
+                                        limit,
+                                        policy,
+                                    });
+                                match policy {
+                                    LimitPolicy::Truncate => {
+                                        y_window = y_window.min(limit.max(1));
+                                        window = (limit / y_window).clamp(1, window)
+                                    }
+                                    LimitPolicy::Skip | LimitPolicy::Error => return None,
+                                }
+                            }
+                            let (x_head, x_seq) = x_seq.split_at(x_seq.len() - window);
+                            let (y_seq, y_tail) = y_seq.split_at(y_window);
+
+                            num_cells.fetch_add(x_seq.len() * y_seq.len(), Ordering::Relaxed);
+                            let alignment =
+                                pool.with(x_seq, y_seq, |aligner| aligner.local(x_seq, y_seq));
+                            let seq: AlignedSequence<T> =
+                                alignment.reduce(visitor.clone(), x_seq, y_seq).into();
+                            if x_head.is_empty() && y_tail.is_empty() {
+                                seq
+                            } else {
+                                AlignedSequence {
+                                    value: [x_head, &seq.value, y_tail].concat(),
+                                    ..seq
+                                }
+                            }
+                        }
+                        Some(Degradation::ExactOverlap) => {
+                            AlignedSequence::join_overlap(x_seq, y_seq)
+                        }
+                        Some(Degradation::Concat) => AlignedSequence {
+                            num_deleted_x: 0,
+                            num_deleted_y: 0,
+                            value: [x_seq, y_seq].concat(),
+                        },
+                    };
+                    Some(I::build(&x.seq, y, seq))
                 }
                 SequenceMatch::Unmatched => None,
                 SequenceMatch::Custom(seq) => Some(seq),
             }
         };
+        let calculate_candidate =
+            |index: usize, x: &State<I, S, T>, y_index, y: &I, degradation| {
+                let seq = calculate_seq(x, y_index, y, degradation)?;
+                let rank = Rank {
+                    score: Some((self.score_fn)(&seq)?),
+                    index,
+                    len: seq.as_ref().len(),
+                };
+                Some((rank, seq))
+            };
+
         #[cfg(feature = "rayon")]
-        let parallel = |len| len >= 5 * current_num_threads();
+        let find_best = |offset: usize, table: &[State<I, S, T>], y: &I, degradation| {
+            let y_index = offset + table.len();
+            let Parallelism {
+                granularity,
+                min_parallel_len,
+                thread_pool,
+            } = &self.parallelism;
+
+            let find_best = || {
+                let num_threads = current_num_threads();
+                let parallel = table.len() >= min_parallel_len.unwrap_or(5 * num_threads);
+                match granularity {
+                    ParallelGranularity::Partition if parallel => {
+                        // NOTE: `par_chunks` panics on the empty chunks
+                        let chunk_size = table.len().div_ceil(num_threads).max(1);
+                        table
+                            .par_chunks(chunk_size)
+                            .enumerate()
+                            .filter_map(|(chunk, xs)| {
+                                xs.iter()
+                                    .enumerate()
+                                    .filter_map(|(index, x)| {
+                                        let index = offset + chunk * chunk_size + index;
+                                        calculate_candidate(index, x, y_index, y, degradation)
+                                    })
+                                    .max_by_key(|(rank, _)| *rank)
+                            })
+                            .max_by_key(|(rank, _)| *rank)
+                    }
+                    granularity => {
+                        let parallel = parallel && *granularity == ParallelGranularity::Candidate;
+                        CondIterator::new(table, parallel)
+                            .enumerate()
+                            .filter_map(|(index, x)| {
+                                calculate_candidate(offset + index, x, y_index, y, degradation)
+                            })
+                            .max_by_key(|(rank, _)| *rank)
+                    }
+                }
+            };
+            match thread_pool {
+                Some(thread_pool) => thread_pool.install(find_best),
+                None => find_best(),
+            }
+        };
+
+        #[cfg(not(feature = "rayon"))]
+        let find_best = |offset: usize, table: &[State<I, S, T>], y: &I, degradation| {
+            let y_index = offset + table.len();
+            table
+                .iter()
+                .enumerate()
+                .filter_map(|(index, x)| {
+                    calculate_candidate(offset + index, x, y_index, y, degradation)
+                })
+                .max_by_key(|(rank, _)| *rank)
+        };
 
         // Fill the table and find the maximum score
+        let instant = Instant::now();
+        let mut report = ReduceReport::default();
         let inputs = inputs.into_iter();
         let mut table: Vec<State<I, S, T>> = match inputs.size_hint().1 {
             Some(len) => Vec::with_capacity(len),
@@ -191,39 +603,60 @@ where
         };
         for y in inputs {
             let y = y.as_ref();
-            let mut best_state: State<I, S, T> = State::genesis((self.score_fn)(y), y);
-
-            #[cfg(feature = "rayon")]
-            let iter = CondIterator::new(&table, parallel(table.len()));
+            let rank = Rank {
+                score: (self.score_fn)(y),
+                index: table.len(),
+                len: y.as_ref().len(),
+            };
+            let mut best_state: State<I, S, T> = State::genesis(rank, y);
+
+            // Degrade gracefully if we are running out of the budget
+            let degradation = self
+                .budget
+                .degradation(instant.elapsed(), num_cells.load(Ordering::Relaxed));
+            let offset = match degradation {
+                None => 0,
+                Some(Degradation::Lookback | Degradation::ExactOverlap) => {
+                    table.len().saturating_sub(self.budget.lookback)
+                }
+                Some(Degradation::Concat) => table.len().saturating_sub(1),
+            };
+            if degradation.is_some() {
+                report.degradation = report.degradation.max(degradation);
+                report.num_degraded += 1;
+            }
 
-            #[cfg(not(feature = "rayon"))]
-            let iter = table.iter();
+            let best = find_best(offset, &table[offset..], y, degradation);
 
-            if let Some((seq, score)) = iter
-                .filter_map(|x| calculate_seq(x, y))
-                .filter_map(|seq| {
-                    let score = (self.score_fn)(&seq)?;
-                    Some((seq, score))
-                })
-                .max_by_key(|(_, score)| *score)
+            // Stop immediately if any limit is exceeded
+            let mut limit_events = limit_events.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Some(&event) = limit_events
+                .iter()
+                .find(|event| event.policy == LimitPolicy::Error)
             {
-                if best_state
-                    .score
-                    .map(|best_score| score > best_score)
-                    .unwrap_or_default()
-                {
-                    best_state = State::new(score, seq)
+                return Err(LimitError { event });
+            }
+            report.limits.append(&mut limit_events);
+            drop(limit_events);
+
+            if let Some((rank, seq)) = best {
+                if best_state.rank.score.is_some() && rank > best_state.rank {
+                    best_state = State::new(rank, seq)
                 }
             }
             table.push(best_state)
         }
+        report.elapsed = instant.elapsed();
+        report.num_cells = num_cells.load(Ordering::Relaxed);
 
         // Pick up the state that was finally selected
-        // If the scores are the same, we choose the latter.
-        table
+        // NOTE: `rank.index` is the one of the predecessor, so the table position breaks the ties
+        let output = table
             .into_iter()
-            .max_by_key(|state| state.score)
-            .map(|state| state.seq)
+            .enumerate()
+            .max_by_key(|(index, state)| (state.rank.score, *index, state.rank.len))
+            .map(|(_, state)| state.seq);
+        Ok((output, report))
     }
 }
 
@@ -231,6 +664,13 @@ pub trait GreedyMultipleSequenceAlignerItem<T> {
     fn build(x: &Self, y: &Self, seq: AlignedSequence<T>) -> Self
     where
         Self: Sized;
+
+    // NOTE: returns whether the item has been cut to `len` tokens, see `LimitPolicy::Truncate`
+    #[inline]
+    fn truncate(&mut self, len: usize) -> bool {
+        let _ = len;
+        false
+    }
 }
 
 pub trait SequenceAlignment<T> {
@@ -526,6 +966,13 @@ impl<T> AsRef<Self> for AlignedSequence<T> {
     }
 }
 
+impl<T> AsMut<Self> for AlignedSequence<T> {
+    #[inline]
+    fn as_mut(&mut self) -> &mut Self {
+        self
+    }
+}
+
 impl<T> AsRef<[AlignedToken<T>]> for AlignedSequence<T> {
     #[inline]
     fn as_ref(&self) -> &[AlignedToken<T>] {
@@ -554,6 +1001,12 @@ impl<T> GreedyMultipleSequenceAlignerItem<T> for AlignedSequence<T> {
     {
         seq
     }
+
+    #[inline]
+    fn truncate(&mut self, len: usize) -> bool {
+        self.value.truncate(len);
+        true
+    }
 }
 
 impl<T> AlignedSequence<T> {
@@ -561,6 +1014,35 @@ impl<T> AlignedSequence<T> {
         self.value.iter().map(|token| token.count).sum()
     }
 
+    pub fn join_overlap(x: &[AlignedToken<T>], y: &[AlignedToken<T>]) -> Self
+    where
+        AlignedToken<T>: Clone + PartialEq,
+    {
+        // Find the longest suffix of `x` which is exactly the prefix of `y`
+        let len = (0..=x.len().min(y.len()))
+            .rev()
+            .find(|&len| x[x.len() - len..] == y[..len])
+            .unwrap_or_default();
+        let (x, overlap) = x.split_at(x.len() - len);
+
+        Self {
+            num_deleted_x: 0,
+            num_deleted_y: 0,
+            value: {
+                let mut buf = Vec::with_capacity(x.len() + y.len());
+                buf.extend_from_slice(x);
+                buf.extend(overlap.iter().zip(y).map(|(x, y)| AlignedToken {
+                    count: x.count.max(y.count) + 1,
+                    ..x.clone()
+                }));
+                buf.extend_from_slice(&y[len..]);
+                buf
+            },
+        }
+    }
+
+    // NOTE: the separator tokens have no votes (`count: 0`), so that they never outscore
+    // the matched tokens
     pub fn join(&self, other: &Self, sep: Option<&[T]>) -> Self
     where
         T: Clone,
@@ -572,7 +1054,11 @@ impl<T> AlignedSequence<T> {
                 let mut buf = Vec::with_capacity(self.value.len() + other.value.len());
                 buf.extend_from_slice(&self.value);
                 if let Some(sep) = sep {
-                    buf.extend(sep.iter().cloned().map(AlignedToken::new));
+                    buf.extend(
+                        sep.iter()
+                            .cloned()
+                            .map(|data| AlignedToken { count: 0, data }),
+                    );
                 }
                 buf.extend_from_slice(&other.value);
                 buf
@@ -0,0 +1,229 @@
+use std::{fmt, mem, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+use crate::{
+    msa::AlignedSequence,
+    segment::Separator,
+    token::{TokenBoundary, TokenMerge, TokenSeparator, TokenSimilarity, TokenSpan},
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Normalization {
+    pub fold_case: bool,
+    // NOTE: spaces are kept in the surface forms, so the spacing is chosen by vote
+    pub fold_spaces: bool,
+    // NOTE: full-width and other compatibility forms are folded with NFKC
+    pub fold_width: bool,
+    // NOTE: symbols are stripped as well
+    pub strip_punctuation: bool,
+}
+
+impl Default for Normalization {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            fold_case: true,
+            fold_spaces: false,
+            fold_width: true,
+            strip_punctuation: true,
+        }
+    }
+}
+
+impl Normalization {
+    pub fn normalize(&self, text: &str) -> Vec<NormalizedChar> {
+        let mut buf: Vec<NormalizedChar> = Vec::new();
+        let mut pending = String::new();
+        let mut is_spaced = false;
+        for unit in split_units(text) {
+            let mut keys = self.normalize_unit(unit).into_iter();
+            match keys.next() {
+                Some(key) => {
+                    pending.push_str(unit);
+                    buf.push(NormalizedChar::new(key, mem::take(&mut pending)));
+                    buf.extend(keys.map(|key| NormalizedChar::new(key, String::new())));
+                    is_spaced = false;
+                }
+                // Keep the stripped text before the spaces on the preceding token,
+                // and the one after them on the following token
+                None => match buf.last_mut() {
+                    Some(last) if unit.chars().all(char::is_whitespace) => {
+                        last.spacing[0].text.push_str(unit);
+                        is_spaced = true;
+                    }
+                    Some(last) if !is_spaced => last.surfaces[0].text.push_str(unit),
+                    _ => pending.push_str(unit),
+                },
+            }
+        }
+
+        // NOTE: the text which is stripped as a whole is kept as is
+        if !pending.is_empty() {
+            match buf.last_mut() {
+                Some(last) => last.spacing[0].text.push_str(&pending),
+                None => buf.extend(
+                    pending
+                        .chars()
+                        .next()
+                        .map(|key| NormalizedChar::new(key, pending)),
+                ),
+            }
+        }
+        buf
+    }
+
+    fn normalize_unit(&self, unit: &str) -> Vec<char> {
+        let mut text: String = if self.fold_width {
+            unit.nfkc().collect()
+        } else {
+            unit.nfc().collect()
+        };
+        if self.fold_case {
+            text = text.to_lowercase();
+        }
+        text.chars()
+            .filter(|&c| !(self.fold_spaces && c.is_whitespace()))
+            .filter(|&c| !(self.strip_punctuation && is_punctuation(c)))
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NormalizedChar {
+    pub key: char,
+    pub surfaces: Vec<Surface>,
+    // NOTE: the spaces after it, voted apart from the surface
+    pub spacing: Vec<Surface>,
+}
+
+impl NormalizedChar {
+    #[inline]
+    pub fn new(key: char, surface: String) -> Self {
+        Self {
+            key,
+            surfaces: vec![Surface {
+                text: surface,
+                votes: 1,
+            }],
+            spacing: vec![Surface {
+                text: String::new(),
+                votes: 1,
+            }],
+        }
+    }
+
+    #[inline]
+    pub fn surface(&self) -> &str {
+        majority(&self.surfaces)
+    }
+
+    #[inline]
+    pub fn spacing(&self) -> &str {
+        majority(&self.spacing)
+    }
+}
+
+impl PartialEq for NormalizedChar {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl Eq for NormalizedChar {}
+
+impl TokenMerge for NormalizedChar {
+    fn merge(&mut self, other: &Self) {
+        merge_votes(&mut self.surfaces, &other.surfaces);
+        merge_votes(&mut self.spacing, &other.spacing);
+    }
+}
+
+impl TokenSimilarity for NormalizedChar {
+    #[inline]
+    fn similarity(&self, other: &Self) -> Option<f32> {
+        self.key.similarity(&other.key)
+    }
+}
+
+impl TokenSeparator for NormalizedChar {
+    fn separator(separator: &Separator, (t0, t1): (Duration, Duration)) -> Vec<Self> {
+        separator
+            .render(t1.saturating_sub(t0))
+            .chars()
+            .map(|c| Self::new(c, c.into()))
+            .collect()
+    }
+}
+
+impl TokenSpan for NormalizedChar {}
+
+impl TokenBoundary for NormalizedChar {
+    #[inline]
+    fn is_sentence_end(&self) -> bool {
+        self.surface().to_string().is_sentence_end()
+    }
+}
+
+impl fmt::Display for AlignedSequence<NormalizedChar> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value.iter().try_for_each(|s| {
+            s.data.surface().fmt(f)?;
+            s.data.spacing().fmt(f)
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Surface {
+    pub text: String,
+    pub votes: usize,
+}
+
+fn majority(surfaces: &[Surface]) -> &str {
+    // NOTE: ties are broken by the first seen surface
+    surfaces
+        .iter()
+        .rev()
+        .max_by_key(|surface| surface.votes)
+        .map(|surface| surface.text.as_str())
+        .unwrap_or_default()
+}
+
+fn merge_votes(surfaces: &mut Vec<Surface>, others: &[Surface]) {
+    for other in others {
+        match surfaces
+            .iter_mut()
+            .find(|Surface { text, .. }| *text == other.text)
+        {
+            Some(Surface { votes, .. }) => *votes += other.votes,
+            None => surfaces.push(other.clone()),
+        }
+    }
+}
+
+fn split_units(text: &str) -> Vec<&str> {
+    let mut units = Vec::new();
+    let mut start = 0;
+    for (index, c) in text.char_indices() {
+        if index > start && !is_continuation(c) {
+            units.push(&text[start..index]);
+            start = index;
+        }
+    }
+    if start < text.len() {
+        units.push(&text[start..]);
+    }
+    units
+}
+
+fn is_continuation(c: char) -> bool {
+    // NOTE: conjoining Hangul vowels and final consonants compose with the preceding jamo
+    is_combining_mark(c) || ('\u{1160}'..='\u{11FF}').contains(&c)
+}
+
+fn is_punctuation(c: char) -> bool {
+    !c.is_alphanumeric() && !c.is_whitespace() && !is_combining_mark(c)
+}
@@ -0,0 +1,94 @@
+use std::{error::Error, fmt};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    hallucination::{RemovedPhrase, SuppressedLoop, UnmatchedPhrase},
+    limit::{LimitError, LimitEvent},
+    msa::ReduceReport,
+    sequence::SequenceEvent,
+    vocab::EncodeError,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Combined<T> {
+    pub value: Option<T>,
+    pub report: CombineReport,
+}
+
+impl<T> Combined<T> {
+    #[inline]
+    pub fn map<F, R>(self, f: F) -> Combined<R>
+    where
+        F: FnOnce(T) -> R,
+    {
+        let Self { value, report } = self;
+        Combined {
+            value: value.map(f),
+            report,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CombineReport {
+    pub limits: Vec<LimitEvent>,
+    pub loops: Vec<SuppressedLoop>,
+    pub phrases: Vec<RemovedPhrase>,
+    pub reduce: ReduceReport,
+    pub sequence: Vec<SequenceEvent>,
+    pub unmatched_phrases: Vec<UnmatchedPhrase>,
+}
+
+impl CombineReport {
+    pub fn applied_limits(&self) -> impl Iterator<Item = &LimitEvent> {
+        self.limits.iter().chain(&self.reduce.limits)
+    }
+
+    fn applied_limits_mut(&mut self) -> impl Iterator<Item = &mut LimitEvent> {
+        self.limits.iter_mut().chain(&mut self.reduce.limits)
+    }
+
+    // NOTE: maps the indices of the reordered inputs back to the original ones
+    pub(crate) fn restore_indices(&mut self, indices: &[usize]) {
+        self.applied_limits_mut()
+            .for_each(|event| event.index = indices[event.index])
+    }
+
+    #[inline]
+    pub fn is_degraded(&self) -> bool {
+        self.reduce.is_degraded()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CombineError {
+    Limit(LimitError),
+    // NOTE: the combined bytes cannot be tokenized back
+    Encode(EncodeError),
+}
+
+impl From<LimitError> for CombineError {
+    #[inline]
+    fn from(error: LimitError) -> Self {
+        Self::Limit(error)
+    }
+}
+
+impl From<EncodeError> for CombineError {
+    #[inline]
+    fn from(error: EncodeError) -> Self {
+        Self::Encode(error)
+    }
+}
+
+impl fmt::Display for CombineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Limit(error) => error.fmt(f),
+            Self::Encode(error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for CombineError {}
@@ -1,12 +1,17 @@
-use std::{fmt, ops, time::Duration};
+use std::{borrow::Cow, fmt, ops, time::Duration};
 
 use serde::{Deserialize, Serialize};
 
 use crate::{
     msa::{AlignedSequence, AlignedToken, GreedyMultipleSequenceAlignerItem},
-    token::TokenData,
+    time::TimeKey,
+    token::{span_of, TokenBoundary, TokenData, TokenSpan},
 };
 
+pub type Segments<T, K = Duration> = Vec<TimedSegment<Vec<T>, K>>;
+
+pub type TimedSegment<V, K = Duration> = Segment<SegmentKey<K>, V>;
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Segment<K = SegmentKey, V = SegmentValue> {
     pub key: K,
@@ -29,6 +34,13 @@ impl<K, V> AsRef<AlignedSequence<V>> for Segment<K, AlignedSequence<V>> {
     }
 }
 
+impl<K, V> AsMut<AlignedSequence<V>> for Segment<K, AlignedSequence<V>> {
+    #[inline]
+    fn as_mut(&mut self) -> &mut AlignedSequence<V> {
+        &mut self.value
+    }
+}
+
 impl<K, V> AsRef<[AlignedToken<V>]> for Segment<K, AlignedSequence<V>> {
     #[inline]
     fn as_ref(&self) -> &[AlignedToken<V>] {
@@ -52,7 +64,10 @@ where
     }
 }
 
-impl<V> GreedyMultipleSequenceAlignerItem<V> for Segment<SegmentKey, AlignedSequence<V>> {
+impl<K, V> GreedyMultipleSequenceAlignerItem<V> for Segment<SegmentKey<K>, AlignedSequence<V>>
+where
+    K: TimeKey,
+{
     #[inline]
     fn build(x: &Self, y: &Self, value: AlignedSequence<V>) -> Self
     where
@@ -66,18 +81,234 @@ impl<V> GreedyMultipleSequenceAlignerItem<V> for Segment<SegmentKey, AlignedSequ
             value,
         }
     }
+
+    fn truncate(&mut self, len: usize) -> bool {
+        let num_tokens = self.value.value.len();
+        if len < num_tokens {
+            // NOTE: the tokens may not be timed, so the end moves in proportion to the kept ones
+            let duration = self.key.duration().mul_f64(len as f64 / num_tokens as f64);
+            self.key.t1 = self.key.t0.advance(duration);
+            self.value.value.truncate(len);
+        }
+        true
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
-pub struct SegmentKey {
+pub struct SegmentKey<K = Duration> {
     // NOTE: fields are ordered
-    pub t1: Duration,
-    pub t0: Duration,
+    pub t1: K,
+    pub t0: K,
 }
 
-impl SegmentKey {
+impl<K> SegmentKey<K>
+where
+    K: TimeKey,
+{
     pub fn duration(&self) -> Duration {
-        self.t1 - self.t0
+        self.t0.elapsed(&self.t1)
+    }
+}
+
+impl<K, T> Segment<SegmentKey<K>, AlignedSequence<T>>
+where
+    K: TimeKey,
+    T: TokenSpan,
+{
+    // NOTE: the token timestamps are preferred, as the keys may be padded
+    pub fn span(&self) -> (K, K) {
+        span_of(&self.value.value)
+            .and_then(|(t0, t1)| Some((K::from_timestamp(t0)?, K::from_timestamp(t1)?)))
+            .unwrap_or((self.key.t0, self.key.t1))
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GapPolicy {
+    // NOTE: the minimum gap of each separator, in ascending order
+    pub separators: Vec<(Duration, Separator)>,
+}
+
+impl GapPolicy {
+    pub fn separator(&self, gap: Duration) -> Option<&Separator> {
+        self.separators
+            .iter()
+            .rev()
+            .find(|(min_gap, _)| *min_gap <= gap)
+            .map(|(_, separator)| separator)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Separator {
+    Space,
+    Newline,
+    Marker(String),
+    // NOTE: a pause marker with its duration, e.g. `[silence 1.5s]`
+    Silence,
+}
+
+impl Separator {
+    pub fn render(&self, gap: Duration) -> Cow<'_, str> {
+        match self {
+            Self::Space => " ".into(),
+            Self::Newline => "\n".into(),
+            Self::Marker(marker) => marker.into(),
+            Self::Silence => format!("[silence {:.1}s]", gap.as_secs_f32()).into(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SegmentOverlap {
+    // NOTE: the ratio to the shorter one
+    pub min_ratio: f32,
+    // NOTE: the timestamps may jitter by this
+    pub slack: Duration,
+    // NOTE: the keys may be padded, but the sparse tokens may leave out the overlap
+    pub token_spans: bool,
+}
+
+impl Default for SegmentOverlap {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            min_ratio: 0.0,
+            slack: Duration::ZERO,
+            token_spans: false,
+        }
+    }
+}
+
+impl SegmentOverlap {
+    pub fn is_overlapped<K>(&self, a: (K, K), b: (K, K)) -> bool
+    where
+        K: TimeKey,
+    {
+        // NOTE: without a minimum ratio, `b` is overlapped whenever it starts before `a` ends,
+        //       even if it lies entirely before `a`, as the plain comparison of the keys
+        if self.min_ratio <= 0.0 {
+            return b.0 < a.1.advance(self.slack);
+        }
+
+        let t0 = a.0.max(b.0);
+        let t1 = a.1.min(b.1);
+        let overlap = if t1 > t0 {
+            t0.elapsed(&t1) + self.slack
+        } else {
+            match self.slack.checked_sub(t1.elapsed(&t0)) {
+                Some(overlap) if !overlap.is_zero() => overlap,
+                _ => return false,
+            }
+        };
+
+        let len = a.0.elapsed(&a.1).min(b.0.elapsed(&b.1));
+        len.is_zero() || overlap.as_secs_f32() >= self.min_ratio * len.as_secs_f32()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SegmentSplit {
+    // NOTE: the edges of the input segments
+    pub at_edges: bool,
+    pub at_punctuation: bool,
+    pub min_silence: Option<Duration>,
+}
+
+impl Default for SegmentSplit {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            at_edges: true,
+            at_punctuation: true,
+            min_silence: Some(Duration::from_millis(500)),
+        }
+    }
+}
+
+impl SegmentSplit {
+    pub fn split<K, T>(
+        &self,
+        segment: Segment<SegmentKey<K>, Vec<T>>,
+        edges: &[K],
+    ) -> Segments<T, K>
+    where
+        K: TimeKey,
+        T: TokenBoundary + TokenSpan,
+    {
+        let Self {
+            at_edges,
+            at_punctuation,
+            min_silence,
+        } = *self;
+        let Segment { key, value } = segment;
+
+        // NOTE: the tokens without spans are keyed by interpolating the merged key
+        let len = value.len().max(1) as f64;
+        let interpolate = |index: usize| key.t0.advance(key.duration().mul_f64(index as f64 / len));
+
+        let mut bounds = vec![0];
+        let mut is_pending_edge = false;
+        for index in 1..value.len() {
+            let (prev, next) = (&value[index - 1], &value[index]);
+            let is_boundary = if at_punctuation && prev.is_sentence_end() && !next.is_sentence_end()
+            {
+                true
+            } else if let (Some((_, prev_t1)), Some((next_t0, _))) = (prev.span(), next.span()) {
+                let is_edge = |&edge: &K| {
+                    K::from_timestamp(prev_t1).is_some_and(|t1| t1 <= edge)
+                        && K::from_timestamp(next_t0).is_some_and(|t0| edge <= t0)
+                };
+                min_silence
+                    .is_some_and(|min_silence| next_t0.saturating_sub(prev_t1) >= min_silence)
+                    || (at_edges && edges.iter().any(is_edge))
+            } else {
+                // NOTE: the untimed tokens have no silence, and are split at the edges
+                //       on the nearest following whitespace
+                let (t0, t1) = (interpolate(index - 1), interpolate(index));
+                is_pending_edge |= at_edges && edges.iter().any(|&edge| t0 < edge && edge <= t1);
+                is_pending_edge && (prev.is_whitespace() || next.is_whitespace())
+            };
+            if is_boundary {
+                bounds.push(index);
+                is_pending_edge = false;
+            }
+        }
+        bounds.push(value.len());
+
+        let mut value = value.into_iter();
+        bounds
+            .windows(2)
+            .filter_map(|window| {
+                let (start, end) = (window[0], window[1]);
+                let mut value: Vec<_> = value.by_ref().take(end - start).collect();
+
+                // NOTE: the pieces do not keep the whitespace around them
+                let num_trailing = value.iter().rev().take_while(|t| t.is_whitespace()).count();
+                value.truncate(value.len() - num_trailing);
+                let num_leading = value.iter().take_while(|t| t.is_whitespace()).count();
+                value.drain(..num_leading);
+                if value.is_empty() {
+                    return None;
+                }
+                let (start, end) = (start + num_leading, end - num_trailing);
+
+                let key = match (
+                    value.first().and_then(T::span),
+                    value.last().and_then(T::span),
+                ) {
+                    (Some((t0, _)), Some((_, t1))) => SegmentKey {
+                        t0: K::from_timestamp(t0).unwrap_or_else(|| interpolate(start)),
+                        t1: K::from_timestamp(t1).unwrap_or_else(|| interpolate(end)),
+                    },
+                    _ => SegmentKey {
+                        t0: interpolate(start),
+                        t1: interpolate(end),
+                    },
+                };
+                Some(Segment { key, value })
+            })
+            .collect()
     }
 }
 
@@ -92,6 +323,35 @@ pub enum SegmentKind {
     Selected,
 }
 
+impl SegmentKind {
+    pub fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (
+                Self::Normal {
+                    last_index: a_index,
+                    last_offset: a_offset,
+                    total_period: a_period,
+                },
+                Self::Normal {
+                    last_index: b_index,
+                    last_offset: b_offset,
+                    total_period: b_period,
+                },
+            ) => Self::Normal {
+                last_index: a_index.max(b_index),
+                last_offset: if a_index > b_index {
+                    a_offset
+                } else {
+                    b_offset
+                },
+                total_period: a_period.max(b_period),
+            },
+            // NOTE: the merged one is selected only if all of them are selected
+            (kind @ Self::Normal { .. }, Self::Selected) | (Self::Selected, kind) => kind,
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct SegmentValue {
     pub kind: SegmentKind,
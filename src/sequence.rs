@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    segment::{Segment, SegmentKey, SegmentKind, SegmentValue},
+    time::TimeKey,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SequenceEvent {
+    Duplicated { index: usize, last_index: usize },
+    Missing { after_index: usize, len: usize },
+    Reordered { index: usize, last_index: usize },
+    Restarted { index: usize, last_index: usize },
+}
+
+// NOTE: returns the input indices of the ordered segments
+pub fn order_segments<I, K>(
+    inputs: I,
+    events: &mut Vec<SequenceEvent>,
+) -> Vec<(usize, Segment<SegmentKey<K>, SegmentValue>)>
+where
+    I: IntoIterator<Item = Segment<SegmentKey<K>, SegmentValue>>,
+    K: TimeKey,
+{
+    #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+    struct Order {
+        epoch: usize,
+        last_index: usize,
+        last_offset: Option<Duration>,
+    }
+
+    // Tell apart the restarts from the late segments
+    // NOTE: a segment starts its stream at `t1 - total_period`, so a restart begins after the
+    // last segment seen and a late segment goes to the last epoch begun before its stream
+    let mut epochs: Vec<K> = Vec::new();
+    let mut last: Option<(Order, SegmentKey<K>)> = None;
+    let mut segments: Vec<_> = inputs
+        .into_iter()
+        .enumerate()
+        .map(|(index, segment)| {
+            let order = match segment.kind {
+                SegmentKind::Normal {
+                    last_index,
+                    last_offset,
+                    total_period,
+                } => {
+                    let begins_after = |t: &K| segment.key.t1 >= t.advance(total_period);
+                    if let Some((last, last_key)) = &last {
+                        if last_index < last.last_index {
+                            if begins_after(&last_key.t1) {
+                                epochs.push(last_key.t1);
+                                events.push(SequenceEvent::Restarted { index, last_index });
+                            } else {
+                                events.push(SequenceEvent::Reordered { index, last_index });
+                            }
+                        }
+                    }
+                    let epoch = epochs.iter().rposition(begins_after).map_or(0, |i| i + 1);
+                    let order = Order {
+                        epoch,
+                        last_index,
+                        last_offset,
+                    };
+                    if last.as_ref().map_or(true, |(last, _)| *last <= order) {
+                        last = Some((order, segment.key.clone()));
+                    }
+                    Some(order)
+                }
+                // NOTE: the selected segments follow the preceding one
+                SegmentKind::Selected => None,
+            };
+            (
+                order.or(last.as_ref().map(|(last, _)| *last)),
+                index,
+                segment,
+            )
+        })
+        .collect();
+    segments.sort_by_key(|(order, _, _)| *order);
+
+    // Find the duplicated and missing chunks
+    let mut outputs: Vec<(usize, Segment<_, _>)> = Vec::with_capacity(segments.len());
+    let mut last: Option<Order> = None;
+    for (order, index, segment) in segments {
+        let is_normal = matches!(segment.kind, SegmentKind::Normal { .. });
+        if let (true, Some(order), Some(last)) = (is_normal, order, last) {
+            if order.epoch == last.epoch {
+                if order == last {
+                    events.push(SequenceEvent::Duplicated {
+                        index,
+                        last_index: order.last_index,
+                    });
+                    continue;
+                }
+                if order.last_index > last.last_index + 1 {
+                    events.push(SequenceEvent::Missing {
+                        after_index: last.last_index,
+                        len: order.last_index - last.last_index - 1,
+                    });
+                }
+            }
+        }
+        if is_normal {
+            last = order;
+        }
+        outputs.push((index, segment));
+    }
+    outputs
+}
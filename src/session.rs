@@ -0,0 +1,238 @@
+use std::{sync::OnceLock, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    limit::LimitError,
+    msa::{AlignedToken, AlignerPool},
+    report::Combined,
+    segment::{Segment, TimedSegment},
+    time::TimeKey,
+    token::Token,
+    StringCombiner, TokenScorer,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct SegmentId(pub usize);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Insertion {
+    InOrder(SegmentId),
+    Reordered(SegmentId),
+    Dropped,
+}
+
+impl Insertion {
+    #[inline]
+    pub const fn id(&self) -> Option<SegmentId> {
+        match self {
+            Self::InOrder(id) | Self::Reordered(id) => Some(*id),
+            Self::Dropped => None,
+        }
+    }
+}
+
+// NOTE: every combination replays all the kept segments in order, so the reordered ones
+//       are merged as if they came in order; only the lateness bounds what is kept
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Reorder {
+    // NOTE: the segments ending earlier than this behind the latest end are dropped
+    pub lateness: Duration,
+}
+
+impl Default for Reorder {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            lateness: Duration::from_secs(2),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Entry<T, K> {
+    id: SegmentId,
+    segment: TimedSegment<Vec<T>, K>,
+}
+
+#[derive(Clone, Debug)]
+pub struct CombineSession<T, K = Duration>
+where
+    T: Send,
+{
+    combiner: StringCombiner<K>,
+    entries: Vec<Entry<T, K>>,
+    next_id: usize,
+    // NOTE: built on the first combination, and reused by the later ones
+    pool: OnceLock<AlignerPool<TokenScorer<T>, AlignedToken<T>>>,
+    reorder: Reorder,
+    watermark: Option<K>,
+}
+
+impl<T, K> Default for CombineSession<T, K>
+where
+    K: TimeKey,
+    T: Send,
+{
+    #[inline]
+    fn default() -> Self {
+        Self::new(StringCombiner::default().with_time_key())
+    }
+}
+
+impl<T, K> CombineSession<T, K>
+where
+    K: TimeKey,
+    T: Send,
+{
+    #[inline]
+    pub fn new(combiner: StringCombiner<K>) -> Self {
+        Self {
+            combiner,
+            entries: Vec::new(),
+            next_id: 0,
+            pool: OnceLock::new(),
+            reorder: Reorder::default(),
+            watermark: None,
+        }
+    }
+
+    #[inline]
+    pub fn with_reorder(mut self, reorder: Reorder) -> Self {
+        self.reorder = reorder;
+        self
+    }
+
+    #[inline]
+    pub fn combiner(&self) -> &StringCombiner<K> {
+        &self.combiner
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, id: SegmentId) -> Option<&TimedSegment<Vec<T>, K>> {
+        self.position(id).map(|index| &self.entries[index].segment)
+    }
+
+    pub fn push<IT>(&mut self, segment: TimedSegment<IT, K>) -> Insertion
+    where
+        IT: IntoIterator<Item = T>,
+    {
+        if !self.advance_watermark(&segment) {
+            return Insertion::Dropped;
+        }
+        let id = self.next_id();
+        self.entries.push(Entry {
+            id,
+            segment: collect(segment),
+        });
+        Insertion::InOrder(id)
+    }
+
+    // NOTE: the segments are ordered by `SegmentKey`, unlike `push` which just appends them
+    pub fn insert<IT>(&mut self, segment: TimedSegment<IT, K>) -> Insertion
+    where
+        IT: IntoIterator<Item = T>,
+    {
+        if !self.advance_watermark(&segment) {
+            return Insertion::Dropped;
+        }
+        let index = self
+            .entries
+            .iter()
+            .position(|entry| entry.segment.key > segment.key)
+            .unwrap_or(self.entries.len());
+        let id = self.next_id();
+        self.entries.insert(
+            index,
+            Entry {
+                id,
+                segment: collect(segment),
+            },
+        );
+
+        if index < self.entries.len() - 1 {
+            Insertion::Reordered(id)
+        } else {
+            Insertion::InOrder(id)
+        }
+    }
+
+    // NOTE: the superseded hypothesis (e.g. an interim result) no longer contributes
+    pub fn replace<IT>(&mut self, id: SegmentId, segment: TimedSegment<IT, K>) -> Option<SegmentId>
+    where
+        IT: IntoIterator<Item = T>,
+    {
+        let index = self.position(id)?;
+        let id = self.next_id();
+        self.entries[index] = Entry {
+            id,
+            segment: collect(segment),
+        };
+        Some(id)
+    }
+
+    // NOTE: its votes are retracted at the next combination
+    pub fn remove(&mut self, id: SegmentId) -> Option<TimedSegment<Vec<T>, K>> {
+        let index = self.position(id)?;
+        Some(self.entries.remove(index).segment)
+    }
+
+    #[inline]
+    pub fn concat(&self) -> Option<TimedSegment<Vec<T>, K>>
+    where
+        T: Send + Sync + Clone + Eq + Token,
+    {
+        self.combine().ok()?.value
+    }
+
+    pub fn combine(&self) -> Result<Combined<TimedSegment<Vec<T>, K>>, LimitError>
+    where
+        T: Send + Sync + Clone + Eq + Token,
+    {
+        let pool = self.pool.get_or_init(|| self.combiner.token_aligner_pool());
+        let inputs = self.entries.iter().map(|entry| entry.segment.clone());
+        self.combiner.combine_token_segments_with_pool(pool, inputs)
+    }
+
+    // NOTE: returns whether the segment is within the lateness
+    fn advance_watermark<V>(&mut self, segment: &TimedSegment<V, K>) -> bool {
+        let lateness = self.reorder.lateness;
+        let is_late = self
+            .watermark
+            .is_some_and(|watermark| segment.key.t1.elapsed(&watermark) > lateness);
+        if !is_late {
+            self.watermark = self.watermark.max(Some(segment.key.t1));
+        }
+        !is_late
+    }
+
+    fn next_id(&mut self) -> SegmentId {
+        let id = SegmentId(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    fn position(&self, id: SegmentId) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.id == id)
+    }
+}
+
+fn collect<K, IT>(segment: TimedSegment<IT, K>) -> TimedSegment<Vec<IT::Item>, K>
+where
+    IT: IntoIterator,
+{
+    let Segment { key, value } = segment;
+    Segment {
+        key,
+        value: value.into_iter().collect(),
+    }
+}
@@ -0,0 +1,120 @@
+use std::{
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+
+pub trait TimeKey: Copy + Ord + Send + Sync {
+    // NOTE: saturates to zero if `later` is earlier than `self`
+    fn elapsed(&self, later: &Self) -> Duration;
+
+    fn advance(&self, duration: Duration) -> Self;
+
+    // NOTE: the token timestamps are relative to the start of the stream
+    #[inline]
+    fn from_timestamp(_timestamp: Duration) -> Option<Self> {
+        None
+    }
+}
+
+impl TimeKey for Duration {
+    #[inline]
+    fn elapsed(&self, later: &Self) -> Duration {
+        later.saturating_sub(*self)
+    }
+
+    #[inline]
+    fn advance(&self, duration: Duration) -> Self {
+        *self + duration
+    }
+
+    #[inline]
+    fn from_timestamp(timestamp: Duration) -> Option<Self> {
+        Some(timestamp)
+    }
+}
+
+impl TimeKey for SystemTime {
+    #[inline]
+    fn elapsed(&self, later: &Self) -> Duration {
+        later.duration_since(*self).unwrap_or_default()
+    }
+
+    #[inline]
+    fn advance(&self, duration: Duration) -> Self {
+        *self + duration
+    }
+}
+
+// NOTE: an index counted at `RATE` per second, e.g. audio samples or video frames
+#[derive(
+    Copy, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub struct Ticks<const RATE: u64>(pub u64);
+
+impl<const RATE: u64> Ticks<RATE> {
+    // NOTE: evaluated on use, so `Ticks<0>` fails to compile
+    const RATE_IS_NONZERO: () = assert!(RATE > 0, "`Ticks` requires a nonzero `RATE`");
+
+    #[inline]
+    pub fn to_duration(self) -> Duration {
+        let () = Self::RATE_IS_NONZERO;
+        let nanos = u128::from(self.0 % RATE) * 1_000_000_000 / u128::from(RATE);
+        Duration::from_secs(self.0 / RATE) + Duration::from_nanos(nanos as u64)
+    }
+
+    // NOTE: saturates to `u64::MAX` ticks
+    #[inline]
+    pub fn from_duration(duration: Duration) -> Self {
+        let () = Self::RATE_IS_NONZERO;
+        let subsec = u128::from(duration.subsec_nanos()) * u128::from(RATE) / 1_000_000_000;
+        Self(
+            duration
+                .as_secs()
+                .saturating_mul(RATE)
+                .saturating_add(subsec as u64),
+        )
+    }
+}
+
+impl<const RATE: u64> TimeKey for Ticks<RATE> {
+    #[inline]
+    fn elapsed(&self, later: &Self) -> Duration {
+        Self(later.0.saturating_sub(self.0)).to_duration()
+    }
+
+    #[inline]
+    fn advance(&self, duration: Duration) -> Self {
+        Self(self.0.saturating_add(Self::from_duration(duration).0))
+    }
+
+    #[inline]
+    fn from_timestamp(timestamp: Duration) -> Option<Self> {
+        Some(Self::from_duration(timestamp))
+    }
+}
+
+// NOTE: counted in milliseconds, as `Ticks<1000>`
+impl TimeKey for u64 {
+    #[inline]
+    fn elapsed(&self, later: &Self) -> Duration {
+        Ticks::<1000>(*self).elapsed(&Ticks(*later))
+    }
+
+    #[inline]
+    fn advance(&self, duration: Duration) -> Self {
+        Ticks::<1000>(*self).advance(duration).0
+    }
+
+    #[inline]
+    fn from_timestamp(timestamp: Duration) -> Option<Self> {
+        Ticks::<1000>::from_timestamp(timestamp).map(|ticks| ticks.0)
+    }
+}
+
+impl<const RATE: u64> fmt::Display for Ticks<RATE> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
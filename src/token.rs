@@ -1,8 +1,23 @@
-use std::time::Duration;
+use std::{
+    fmt, mem,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
 
-use crate::msa::{AlignedSequence, AlignedToken, AlignmentVisitor};
+use crate::{
+    hangul,
+    limit::Limits,
+    msa::{
+        AlignedSequence, AlignedToken, AlignerPool, AlignmentVisitor, Budget, SequenceAlignment,
+    },
+    segment::Separator,
+};
 
 #[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct TokenData {
@@ -20,10 +35,587 @@ impl PartialEq for TokenData {
 
 impl Eq for TokenData {}
 
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct TimedByte {
+    pub byte: u8,
+    // NOTE: `char::REPLACEMENT_CHARACTER` if the byte is not a part of valid UTF-8
+    pub ch: char,
+    // NOTE: the byte offset in the encoded `ch`
+    pub offset: u8,
+    pub t0: Duration,
+    pub t1: Duration,
+}
+
+impl TimedByte {
+    pub fn split(bytes: &[(u8, Duration, Duration)]) -> Vec<Self> {
+        let text: Vec<_> = bytes.iter().map(|&(byte, _, _)| byte).collect();
+
+        // Tag each byte with the character it belongs to
+        let mut chars = Vec::with_capacity(text.len());
+        let mut rest = text.as_slice();
+        while !rest.is_empty() {
+            let (valid, len_invalid) = match ::std::str::from_utf8(rest) {
+                Ok(valid) => (valid, 0),
+                Err(error) => {
+                    let (valid, invalid) = rest.split_at(error.valid_up_to());
+                    let valid = ::std::str::from_utf8(valid).unwrap_or_default();
+                    (valid, error.error_len().unwrap_or(invalid.len()))
+                }
+            };
+            chars.extend(
+                valid
+                    .chars()
+                    .flat_map(|ch| (0..ch.len_utf8()).map(move |offset| (ch, offset as u8))),
+            );
+            chars.extend((0..len_invalid).map(|_| (char::REPLACEMENT_CHARACTER, 0)));
+            rest = &rest[valid.len() + len_invalid..];
+        }
+
+        bytes
+            .iter()
+            .zip(chars)
+            .map(|(&(byte, t0, t1), (ch, offset))| Self {
+                byte,
+                ch,
+                offset,
+                t0,
+                t1,
+            })
+            .collect()
+    }
+
+    // NOTE: the characters split by the merge are written as a whole
+    pub fn repair(bytes: &[Self]) -> Vec<Self> {
+        let mut buf = Vec::with_capacity(bytes.len());
+        let mut last: Option<(char, u8)> = None;
+        for &token in bytes {
+            let Self { ch, offset, .. } = token;
+            let mut encoded = [0; 4];
+            let encoded = ch.encode_utf8(&mut encoded).as_bytes();
+            let is_continuation = offset > 0
+                && last.is_some_and(|(last, last_offset)| last == ch && last_offset < offset);
+            last = Some((ch, offset));
+
+            if encoded.get(offset as usize) != Some(&token.byte) {
+                // The invalid bytes are kept as they are
+                buf.push(token);
+            } else if !is_continuation {
+                buf.extend(encoded.iter().enumerate().map(|(offset, &byte)| Self {
+                    byte,
+                    offset: offset as u8,
+                    ..token
+                }));
+            }
+        }
+        buf
+    }
+}
+
+impl PartialEq for TimedByte {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        // NOTE: the bytes of the different characters never match
+        self.byte == other.byte && self.ch == other.ch
+    }
+}
+
+impl Eq for TimedByte {}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Anchored<T> {
+    pub anchored: bool,
+    pub data: T,
+}
+
+impl<T> PartialEq for Anchored<T>
+where
+    T: PartialEq,
+{
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.data == other.data
+    }
+}
+
+impl<T> Eq for Anchored<T> where T: Eq {}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Grapheme(pub String);
+
+impl Grapheme {
+    pub fn split(text: &str) -> impl Iterator<Item = Self> + '_ {
+        text.graphemes(true).map(|g| Self(g.into()))
+    }
+}
+
+impl fmt::Display for Grapheme {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Display for AlignedSequence<Grapheme> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value.iter().try_for_each(|s| s.data.fmt(f))
+    }
+}
+
+impl fmt::Display for AlignedSequence<String> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value.iter().try_for_each(|s| s.data.fmt(f))
+    }
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Utf8Byte {
+    pub byte: u8,
+    pub ch: char,
+    // NOTE: the byte offset in the encoded `ch`
+    pub offset: u8,
+}
+
+impl Utf8Byte {
+    pub fn split(text: &str) -> impl Iterator<Item = Self> + '_ {
+        text.chars().flat_map(|ch| {
+            let mut buf = [0; 4];
+            let len = ch.encode_utf8(&mut buf).len();
+            (0..len).map(move |offset| Self {
+                byte: buf[offset],
+                ch,
+                offset: offset as u8,
+            })
+        })
+    }
+}
+
+impl PartialEq for Utf8Byte {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        // NOTE: the bytes of the different characters never match
+        self.byte == other.byte && self.ch == other.ch
+    }
+}
+
+impl Eq for Utf8Byte {}
+
+impl fmt::Display for AlignedSequence<Utf8Byte> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Repair the characters split by the merge, writing one of them per code point
+        let mut votes: Vec<(char, usize)> = Vec::new();
+        let mut is_complete = false;
+        for &AlignedToken { count, data } in &self.value {
+            let Utf8Byte { ch, offset, .. } = data;
+            let len = ch.len_utf8();
+
+            // NOTE: the lead bytes of the same width are interleaved until the code point ends
+            let is_interleaved = !is_complete
+                && len > 1
+                && votes
+                    .first()
+                    .is_some_and(|(first, _)| first.len_utf8() == len);
+            if offset == 0 && !is_interleaved {
+                write_majority(&votes, f)?;
+                votes.clear();
+                is_complete = false;
+            }
+            match votes.iter_mut().find(|(last, _)| *last == ch) {
+                Some((_, total)) => *total += count,
+                None => votes.push((ch, count)),
+            }
+            is_complete |= usize::from(offset) + 1 == len;
+        }
+        write_majority(&votes, f)
+    }
+}
+
+fn write_majority(votes: &[(char, usize)], f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    // NOTE: the first character wins the ties
+    let majority = votes
+        .iter()
+        .fold(None, |best: Option<&(char, usize)>, vote| match best {
+            Some(best) if best.1 >= vote.1 => Some(best),
+            _ => Some(vote),
+        });
+    match majority {
+        Some((ch, _)) => fmt::Display::fmt(ch, f),
+        None => Ok(()),
+    }
+}
+
+pub trait TokenSpan {
+    #[inline]
+    fn span(&self) -> Option<(Duration, Duration)> {
+        None
+    }
+}
+
+impl<T> TokenSpan for Anchored<T>
+where
+    T: TokenSpan,
+{
+    #[inline]
+    fn span(&self) -> Option<(Duration, Duration)> {
+        self.data.span()
+    }
+}
+
+impl TokenSpan for u8 {}
+
+impl TokenSpan for char {}
+
+impl TokenSpan for Grapheme {}
+
+impl TokenSpan for Utf8Byte {}
+
+impl TokenSpan for String {}
+
+impl TokenSpan for TimedByte {
+    #[inline]
+    fn span(&self) -> Option<(Duration, Duration)> {
+        Some((self.t0, self.t1))
+    }
+}
+
+impl TokenSpan for TokenData {
+    #[inline]
+    fn span(&self) -> Option<(Duration, Duration)> {
+        Some((self.t0, self.t1))
+    }
+}
+
+pub(crate) fn span_of<T>(tokens: &[AlignedToken<T>]) -> Option<(Duration, Duration)>
+where
+    T: TokenSpan,
+{
+    tokens
+        .iter()
+        .filter_map(|token| token.data.span())
+        .reduce(|(a0, a1), (b0, b1)| (a0.min(b0), a1.max(b1)))
+}
+
+pub trait TokenMerge {
+    // NOTE: called when `other` is matched against `self`
+    #[inline]
+    fn merge(&mut self, _other: &Self) {}
+
+    // NOTE: the anchored tokens always win substitutions and are never deleted
+    #[inline]
+    fn is_anchored(&self) -> bool {
+        false
+    }
+}
+
+impl<T> TokenMerge for Anchored<T>
+where
+    T: Clone + TokenMerge,
+{
+    fn merge(&mut self, other: &Self) {
+        if other.anchored && !self.anchored {
+            *self = other.clone();
+        } else {
+            self.data.merge(&other.data);
+        }
+    }
+
+    #[inline]
+    fn is_anchored(&self) -> bool {
+        self.anchored
+    }
+}
+
+impl TokenMerge for u8 {}
+
+impl TokenMerge for char {}
+
+impl TokenMerge for Grapheme {}
+
+impl TokenMerge for Utf8Byte {}
+
+impl TokenMerge for String {}
+
+impl TokenMerge for TimedByte {}
+
+impl TokenMerge for TokenData {}
+
+pub trait TokenSimilarity {
+    // NOTE: in `0.0..=1.0`, for the tokens which are not equal
+    #[inline]
+    fn similarity(&self, _other: &Self) -> Option<f32> {
+        None
+    }
+}
+
+impl<T> TokenSimilarity for Anchored<T>
+where
+    T: TokenSimilarity,
+{
+    #[inline]
+    fn similarity(&self, other: &Self) -> Option<f32> {
+        self.data.similarity(&other.data)
+    }
+}
+
+impl TokenSimilarity for u8 {}
+
+impl TokenSimilarity for char {
+    #[inline]
+    fn similarity(&self, other: &Self) -> Option<f32> {
+        hangul::similarity(*self, *other)
+    }
+}
+
+impl TokenSimilarity for Grapheme {
+    fn similarity(&self, other: &Self) -> Option<f32> {
+        let mut a = self.0.chars();
+        let mut b = other.0.chars();
+        match (a.next(), a.next(), b.next(), b.next()) {
+            (Some(a), None, Some(b), None) => a.similarity(&b),
+            _ => None,
+        }
+    }
+}
+
+impl TokenSimilarity for Utf8Byte {
+    #[inline]
+    fn similarity(&self, other: &Self) -> Option<f32> {
+        self.ch.similarity(&other.ch)
+    }
+}
+
+impl TokenSimilarity for String {
+    fn similarity(&self, other: &Self) -> Option<f32> {
+        let a: Vec<_> = self.chars().collect();
+        let b: Vec<_> = other.chars().collect();
+        let len = a.len().max(b.len());
+        if len == 0 {
+            return None;
+        }
+        Some(1.0 - edit_distance(&a, &b) as f32 / len as f32)
+    }
+}
+
+impl TokenSimilarity for TimedByte {
+    #[inline]
+    fn similarity(&self, other: &Self) -> Option<f32> {
+        self.ch.similarity(&other.ch)
+    }
+}
+
+impl TokenSimilarity for TokenData {}
+
+fn edit_distance<T>(a: &[T], b: &[T]) -> usize
+where
+    T: PartialEq,
+{
+    let mut row: Vec<_> = (0..=b.len()).collect();
+    for (i, a) in a.iter().enumerate() {
+        let mut diag = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let cost = if a == b { diag } else { diag + 1 };
+            diag = row[j + 1];
+            row[j + 1] = cost.min(row[j] + 1).min(diag + 1);
+        }
+    }
+    row[b.len()]
+}
+
+pub trait TokenBoundary {
+    #[inline]
+    fn is_sentence_end(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    fn is_whitespace(&self) -> bool {
+        false
+    }
+}
+
+impl<T> TokenBoundary for Anchored<T>
+where
+    T: TokenBoundary,
+{
+    #[inline]
+    fn is_sentence_end(&self) -> bool {
+        self.data.is_sentence_end()
+    }
+
+    #[inline]
+    fn is_whitespace(&self) -> bool {
+        self.data.is_whitespace()
+    }
+}
+
+impl TokenBoundary for u8 {
+    #[inline]
+    fn is_sentence_end(&self) -> bool {
+        char::from(*self).is_sentence_end()
+    }
+
+    #[inline]
+    fn is_whitespace(&self) -> bool {
+        self.is_ascii_whitespace()
+    }
+}
+
+impl TokenBoundary for char {
+    #[inline]
+    fn is_sentence_end(&self) -> bool {
+        matches!(
+            self,
+            '.' | '?' | '!' | '\u{2026}' | '\u{3002}' | '\u{ff1f}' | '\u{ff01}'
+        )
+    }
+
+    #[inline]
+    fn is_whitespace(&self) -> bool {
+        char::is_whitespace(*self)
+    }
+}
+
+impl TokenBoundary for Grapheme {
+    #[inline]
+    fn is_sentence_end(&self) -> bool {
+        self.0.is_sentence_end()
+    }
+
+    #[inline]
+    fn is_whitespace(&self) -> bool {
+        self.0.is_whitespace()
+    }
+}
+
+impl TokenBoundary for Utf8Byte {
+    #[inline]
+    fn is_sentence_end(&self) -> bool {
+        self.ch.is_sentence_end()
+    }
+
+    #[inline]
+    fn is_whitespace(&self) -> bool {
+        self.ch.is_whitespace()
+    }
+}
+
+impl TokenBoundary for String {
+    #[inline]
+    fn is_sentence_end(&self) -> bool {
+        self.trim_end()
+            .chars()
+            .last()
+            .is_some_and(|c| c.is_sentence_end())
+    }
+
+    #[inline]
+    fn is_whitespace(&self) -> bool {
+        !self.is_empty() && self.chars().all(char::is_whitespace)
+    }
+}
+
+impl TokenBoundary for TimedByte {
+    #[inline]
+    fn is_sentence_end(&self) -> bool {
+        self.byte.is_sentence_end()
+    }
+
+    #[inline]
+    fn is_whitespace(&self) -> bool {
+        self.byte.is_whitespace()
+    }
+}
+
+impl TokenBoundary for TokenData {}
+
+pub trait TokenSeparator: Sized {
+    // NOTE: the tokens inserted between the disjoint segments, filling the gap `(t0, t1)`
+    #[inline]
+    fn separator(_separator: &Separator, _gap: (Duration, Duration)) -> Vec<Self> {
+        Vec::new()
+    }
+}
+
+impl<T> TokenSeparator for Anchored<T>
+where
+    T: TokenSeparator,
+{
+    fn separator(separator: &Separator, gap: (Duration, Duration)) -> Vec<Self> {
+        T::separator(separator, gap)
+            .into_iter()
+            .map(|data| Self {
+                anchored: false,
+                data,
+            })
+            .collect()
+    }
+}
+
+impl TokenSeparator for u8 {
+    #[inline]
+    fn separator(separator: &Separator, (t0, t1): (Duration, Duration)) -> Vec<Self> {
+        separator.render(t1.saturating_sub(t0)).bytes().collect()
+    }
+}
+
+impl TokenSeparator for char {
+    #[inline]
+    fn separator(separator: &Separator, (t0, t1): (Duration, Duration)) -> Vec<Self> {
+        separator.render(t1.saturating_sub(t0)).chars().collect()
+    }
+}
+
+impl TokenSeparator for Grapheme {
+    #[inline]
+    fn separator(separator: &Separator, (t0, t1): (Duration, Duration)) -> Vec<Self> {
+        Self::split(&separator.render(t1.saturating_sub(t0))).collect()
+    }
+}
+
+impl TokenSeparator for Utf8Byte {
+    #[inline]
+    fn separator(separator: &Separator, (t0, t1): (Duration, Duration)) -> Vec<Self> {
+        Self::split(&separator.render(t1.saturating_sub(t0))).collect()
+    }
+}
+
+impl TokenSeparator for String {
+    #[inline]
+    fn separator(separator: &Separator, (t0, t1): (Duration, Duration)) -> Vec<Self> {
+        vec![separator.render(t1.saturating_sub(t0)).into_owned()]
+    }
+}
+
+impl TokenSeparator for TimedByte {
+    fn separator(separator: &Separator, (t0, t1): (Duration, Duration)) -> Vec<Self> {
+        // NOTE: the separator is tokenized again along with the others
+        let bytes: Vec<_> = separator
+            .render(t1.saturating_sub(t0))
+            .bytes()
+            .map(|byte| (byte, t0, t1))
+            .collect();
+        Self::split(&bytes)
+    }
+}
+
+// NOTE: the token ids cannot be made up without a vocabulary
+impl TokenSeparator for TokenData {}
+
+pub trait Token: TokenBoundary + TokenSeparator + TokenSpan + TokenMerge + TokenSimilarity {}
+
+impl<T> Token for T where
+    T: TokenBoundary + TokenSeparator + TokenSpan + TokenMerge + TokenSimilarity
+{
+}
+
 #[derive(Clone, Debug)]
 pub struct AlignmentTokenMergeVisitor<T> {
+    // NOTE: the tokens inserted right after an anchored token, until the next kept one
+    after_anchor: bool,
     allow_deletion: bool,
     buf: Vec<AlignmentToken<T>>,
+    is_anchored: fn(&T) -> bool,
+    merge: fn(&mut T, &T),
     num_deleted_x: usize,
     num_deleted_y: usize,
 }
@@ -40,14 +632,59 @@ impl<T> AlignmentTokenMergeVisitor<T> {
     #[inline]
     pub fn new(allow_deletion: bool) -> Self {
         Self {
+            after_anchor: false,
             allow_deletion,
             buf: Default::default(),
+            is_anchored: |_| false,
+            merge: |_, _| (),
             num_deleted_x: 0,
             num_deleted_y: 0,
         }
     }
 }
 
+impl<T> AlignmentTokenMergeVisitor<AlignedToken<T>>
+where
+    T: TokenMerge,
+{
+    // NOTE: the tokens are merged and anchored by `TokenMerge`
+    #[inline]
+    pub fn with_token_merge(allow_deletion: bool) -> Self {
+        Self {
+            is_anchored: |token| token.data.is_anchored(),
+            merge: |token, other| token.data.merge(&other.data),
+            ..Self::new(allow_deletion)
+        }
+    }
+}
+
+impl<T> AlignmentTokenMergeVisitor<AlignedToken<T>>
+where
+    T: Clone,
+{
+    fn push(&mut self, data: AlignedToken<T>, op: AlignmentTokenOp<AlignedToken<T>>) {
+        if !matches!(op, AlignmentTokenOp::Del) {
+            self.after_anchor = (self.is_anchored)(&data);
+        }
+        self.buf.push(AlignmentToken { data, op })
+    }
+
+    fn keep_anchored(
+        &mut self,
+        tokens: &[AlignedToken<T>],
+        op: AlignmentTokenOp<AlignedToken<T>>,
+        mut delete: impl FnMut(&mut Self),
+    ) {
+        for token in tokens {
+            if (self.is_anchored)(token) {
+                self.push(token.clone(), op.clone());
+            } else {
+                delete(self);
+            }
+        }
+    }
+}
+
 impl<T> AlignmentVisitor<AlignedToken<T>> for AlignmentTokenMergeVisitor<AlignedToken<T>>
 where
     T: Clone,
@@ -56,78 +693,77 @@ where
 
     #[inline]
     fn visit_prefix_x(&mut self, x: &[AlignedToken<T>]) {
-        self.buf.extend(
-            x.iter()
-                .cloned()
-                .map(|AlignedToken { count, data }| AlignmentToken {
-                    data: AlignedToken { count, data },
-                    op: AlignmentTokenOp::Prefix,
-                }),
-        )
+        for token in x {
+            self.push(token.clone(), AlignmentTokenOp::Prefix)
+        }
     }
 
     #[inline]
     fn visit_prefix_y(&mut self, y: &[AlignedToken<T>]) {
-        self.num_deleted_y += y.len()
+        self.keep_anchored(y, AlignmentTokenOp::Prefix, |this| this.num_deleted_y += 1)
     }
 
     #[inline]
     fn visit_match(&mut self, x: &AlignedToken<T>, y: &AlignedToken<T>) {
-        self.buf.push(AlignmentToken {
-            data: AlignedToken {
-                count: x.count.max(y.count) + 1,
-                data: x.data.clone(),
-            },
-            op: AlignmentTokenOp::Match,
-        })
+        let mut data = x.clone();
+        (self.merge)(&mut data, y);
+        data.count = x.count.max(y.count) + 1;
+        self.push(data, AlignmentTokenOp::Match)
     }
 
     #[inline]
     fn visit_subst(&mut self, x: &AlignedToken<T>, y: &AlignedToken<T>) {
-        let (data, other) = if x.count >= y.count {
+        let x_wins = match ((self.is_anchored)(x), (self.is_anchored)(y)) {
+            (true, false) => true,
+            (false, true) => false,
+            _ => x.count >= y.count,
+        };
+        let (data, other) = if x_wins {
             self.num_deleted_y += 1;
             (x, y)
         } else {
             self.num_deleted_x += 1;
             (y, x)
         };
-        self.buf.push(AlignmentToken {
-            data: data.clone(),
-            op: AlignmentTokenOp::Subst {
-                other: other.clone(),
-            },
-        })
+        let op = AlignmentTokenOp::Subst {
+            other: other.clone(),
+        };
+        self.push(data.clone(), op)
     }
 
     #[inline]
     fn visit_del(&mut self, y: &AlignedToken<T>) {
-        self.num_deleted_x += 1;
-        self.buf.push(AlignmentToken {
-            data: y.clone(),
-            op: AlignmentTokenOp::Del,
-        })
+        let op = if (self.is_anchored)(y) {
+            AlignmentTokenOp::Ins
+        } else {
+            self.num_deleted_x += 1;
+            AlignmentTokenOp::Del
+        };
+        self.push(y.clone(), op)
     }
 
     #[inline]
     fn visit_ins(&mut self, x: &AlignedToken<T>) {
-        self.buf.push(AlignmentToken {
-            data: x.clone(),
-            op: AlignmentTokenOp::Ins,
-        })
+        // NOTE: the others may not rewrite the anchored tokens
+        let op = if !(self.is_anchored)(x) && self.after_anchor {
+            self.num_deleted_x += 1;
+            AlignmentTokenOp::Del
+        } else {
+            AlignmentTokenOp::Ins
+        };
+        self.push(x.clone(), op)
     }
 
     #[inline]
     fn visit_suffix_x(&mut self, x: &[AlignedToken<T>]) {
-        self.num_deleted_x += x.len()
+        self.keep_anchored(x, AlignmentTokenOp::Ins, |this| this.num_deleted_x += 1)
     }
 
     #[inline]
     fn visit_suffix_y(&mut self, y: &[AlignedToken<T>]) {
-        self.buf
-            .extend(y.iter().cloned().map(|data| AlignmentToken {
-                data,
-                op: AlignmentTokenOp::Ins,
-            }))
+        for token in y {
+            self.push(token.clone(), AlignmentTokenOp::Ins)
+        }
     }
 
     #[inline]
@@ -137,6 +773,7 @@ where
             buf,
             num_deleted_x,
             num_deleted_y,
+            ..
         } = self;
 
         if !allow_deletion
@@ -166,6 +803,204 @@ where
     }
 }
 
+// NOTE: the words are aligned first, and then the characters only inside the mismatched words
+#[derive(Clone)]
+pub struct HierarchicalMergeVisitor<'a, F>
+where
+    F: Send,
+{
+    budget: Budget,
+    inner: AlignmentTokenMergeVisitor<AlignedToken<String>>,
+    limits: Limits,
+    // NOTE: the DP cells of the character realignments, shared by the clones
+    num_cells: Option<Arc<AtomicUsize>>,
+    num_deleted_x: usize,
+    num_deleted_y: usize,
+    pool: &'a AlignerPool<F, AlignedToken<char>>,
+    xs: Vec<AlignedToken<String>>,
+    ys: Vec<AlignedToken<String>>,
+}
+
+impl<'a, F> HierarchicalMergeVisitor<'a, F>
+where
+    F: Send,
+{
+    #[inline]
+    pub fn new(allow_deletion: bool, pool: &'a AlignerPool<F, AlignedToken<char>>) -> Self {
+        Self {
+            budget: Budget::unlimited(),
+            inner: AlignmentTokenMergeVisitor::new(allow_deletion),
+            limits: Limits::unlimited(),
+            num_cells: None,
+            num_deleted_x: 0,
+            num_deleted_y: 0,
+            pool,
+            xs: Vec::new(),
+            ys: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn with_budget(mut self, budget: Budget, num_cells: Arc<AtomicUsize>) -> Self {
+        self.budget = budget;
+        self.num_cells = Some(num_cells);
+        self
+    }
+
+    #[inline]
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+}
+
+impl<'a, F> HierarchicalMergeVisitor<'a, F>
+where
+    F: Send + Clone + Fn(&AlignedToken<char>, &AlignedToken<char>) -> i32,
+{
+    fn flush(&mut self) {
+        fn chars_of(words: &[AlignedToken<String>]) -> Vec<AlignedToken<char>> {
+            words
+                .iter()
+                .flat_map(|AlignedToken { count, data }| {
+                    data.chars().map(|data| AlignedToken {
+                        count: *count,
+                        data,
+                    })
+                })
+                .collect()
+        }
+
+        let xs = mem::take(&mut self.xs);
+        let ys = mem::take(&mut self.ys);
+        if ys.is_empty() {
+            xs.iter().for_each(|x| self.inner.visit_ins(x));
+            return;
+        }
+        if xs.is_empty() {
+            ys.iter().for_each(|y| self.inner.visit_del(y));
+            return;
+        }
+
+        // Merge the mismatched words character by character
+        let x = chars_of(&xs);
+        let y = chars_of(&ys);
+
+        // Keep the word-level merge if the realignment is over the budget
+        let cells = x.len() * y.len();
+        let num_cells = self
+            .num_cells
+            .as_ref()
+            .map_or(0, |num_cells| num_cells.load(Ordering::Relaxed));
+        if self.limits.max_cells.is_some_and(|max| cells > max)
+            || self
+                .budget
+                .max_cells
+                .is_some_and(|max| num_cells + cells > max)
+        {
+            xs.iter()
+                .zip(&ys)
+                .for_each(|(x, y)| self.inner.visit_subst(x, y));
+            xs.iter()
+                .skip(ys.len())
+                .for_each(|x| self.inner.visit_ins(x));
+            ys.iter()
+                .skip(xs.len())
+                .for_each(|y| self.inner.visit_del(y));
+            return;
+        }
+        if let Some(num_cells) = &self.num_cells {
+            num_cells.fetch_add(cells, Ordering::Relaxed);
+        }
+
+        let visitor = AlignmentTokenMergeVisitor::new(self.inner.allow_deletion);
+        let seq = self
+            .pool
+            .with(&x, &y, |aligner| aligner.global(&x, &y))
+            .reduce(visitor, &x, &y);
+        self.num_deleted_x += seq.num_deleted_x;
+        self.num_deleted_y += seq.num_deleted_y;
+
+        // Split them into the words again
+        let text: String = seq.value.iter().map(|token| token.data).collect();
+        let mut chars = seq.value.iter();
+        for word in text.split_word_bounds() {
+            let count = chars
+                .by_ref()
+                .take(word.chars().count())
+                .map(|token| token.count)
+                .min()
+                .unwrap_or(1);
+            self.inner.visit_ins(&AlignedToken {
+                count,
+                data: word.into(),
+            });
+        }
+    }
+}
+
+impl<'a, F> AlignmentVisitor<AlignedToken<String>> for HierarchicalMergeVisitor<'a, F>
+where
+    F: Send + Clone + Fn(&AlignedToken<char>, &AlignedToken<char>) -> i32,
+{
+    type Output = AlignedSequence<String>;
+
+    #[inline]
+    fn visit_prefix_x(&mut self, x: &[AlignedToken<String>]) {
+        self.flush();
+        self.inner.visit_prefix_x(x)
+    }
+
+    #[inline]
+    fn visit_prefix_y(&mut self, y: &[AlignedToken<String>]) {
+        self.flush();
+        self.inner.visit_prefix_y(y)
+    }
+
+    #[inline]
+    fn visit_match(&mut self, x: &AlignedToken<String>, y: &AlignedToken<String>) {
+        self.flush();
+        self.inner.visit_match(x, y)
+    }
+
+    #[inline]
+    fn visit_subst(&mut self, x: &AlignedToken<String>, y: &AlignedToken<String>) {
+        self.xs.push(x.clone());
+        self.ys.push(y.clone());
+    }
+
+    #[inline]
+    fn visit_del(&mut self, y: &AlignedToken<String>) {
+        self.ys.push(y.clone())
+    }
+
+    #[inline]
+    fn visit_ins(&mut self, x: &AlignedToken<String>) {
+        self.xs.push(x.clone())
+    }
+
+    #[inline]
+    fn visit_suffix_x(&mut self, x: &[AlignedToken<String>]) {
+        self.flush();
+        self.inner.visit_suffix_x(x)
+    }
+
+    #[inline]
+    fn visit_suffix_y(&mut self, y: &[AlignedToken<String>]) {
+        self.flush();
+        self.inner.visit_suffix_y(y)
+    }
+
+    #[inline]
+    fn finish(mut self) -> Self::Output {
+        self.flush();
+        let mut seq = self.inner.finish();
+        seq.num_deleted_x += self.num_deleted_x;
+        seq.num_deleted_y += self.num_deleted_y;
+        seq
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 struct AlignmentToken<T> {
     data: T,
@@ -0,0 +1,268 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+
+use crate::token::{TimedByte, TokenData};
+
+#[derive(Clone, Debug, Default)]
+pub struct Vocabulary {
+    decoder: HashMap<i32, Vec<u8>>,
+    encoder: HashMap<Vec<u8>, i32>,
+    // NOTE: the merged bytes to the length of their left half and the rank of the merge;
+    // the token ids are used as the merge ranks if empty (tiktoken)
+    merges: HashMap<Vec<u8>, Vec<(usize, usize)>>,
+}
+
+impl FromIterator<(i32, Vec<u8>)> for Vocabulary {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (i32, Vec<u8>)>,
+    {
+        let decoder: HashMap<_, _> = iter.into_iter().collect();
+
+        // NOTE: the duplicated bytes are encoded into the lowest id
+        let mut encoder = HashMap::with_capacity(decoder.len());
+        for (&id, bytes) in &decoder {
+            encoder
+                .entry(bytes.clone())
+                .and_modify(|last: &mut i32| *last = (*last).min(id))
+                .or_insert(id);
+        }
+        Self {
+            decoder,
+            encoder,
+            merges: HashMap::default(),
+        }
+    }
+}
+
+impl Vocabulary {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file = BufReader::new(File::open(path)?);
+        if path.extension().is_some_and(|ext| ext == "json") {
+            Self::from_tokenizer_json(file)
+        } else {
+            Self::from_tiktoken(file)
+        }
+    }
+
+    pub fn from_tiktoken(reader: impl BufRead) -> io::Result<Self> {
+        let invalid = |line: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid tiktoken line: {line:?}"),
+            )
+        };
+
+        reader
+            .lines()
+            .filter(|line| !line.as_ref().is_ok_and(|line| line.trim().is_empty()))
+            .map(|line| {
+                let line = line?;
+                let (token, rank) = line.split_once(' ').ok_or_else(|| invalid(&line))?;
+                let token = STANDARD.decode(token).map_err(|_| invalid(&line))?;
+                let rank = rank.trim().parse().map_err(|_| invalid(&line))?;
+                Ok((rank, token))
+            })
+            .collect()
+    }
+
+    pub fn from_tokenizer_json(reader: impl Read) -> io::Result<Self> {
+        #[derive(Deserialize)]
+        struct Tokenizer {
+            model: Model,
+        }
+
+        #[derive(Deserialize)]
+        struct Model {
+            vocab: HashMap<String, i32>,
+            #[serde(default)]
+            merges: Vec<Merge>,
+        }
+
+        // NOTE: the merges are either "a b" or ["a", "b"]
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Merge {
+            Joined(String),
+            Pair(String, String),
+        }
+
+        // NOTE: the added (special) tokens are not a part of the text
+        let Tokenizer { model } = ::serde_json::from_reader(reader)?;
+        let unicode_to_byte = unicode_to_byte();
+        let decode = |token: &str| {
+            token
+                .chars()
+                .map(|c| unicode_to_byte.get(&c).copied())
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid byte-level token: {token:?}"),
+                    )
+                })
+        };
+
+        let mut vocab = model
+            .vocab
+            .iter()
+            .map(|(token, &id)| Ok((id, decode(token)?)))
+            .collect::<io::Result<Self>>()?;
+        for (rank, merge) in model.merges.iter().enumerate() {
+            let (left, right) = match merge {
+                Merge::Joined(merge) => merge.split_once(' ').ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid merge: {merge:?}"),
+                    )
+                })?,
+                Merge::Pair(left, right) => (left.as_str(), right.as_str()),
+            };
+            let (mut bytes, right) = (decode(left)?, decode(right)?);
+            let len = bytes.len();
+            bytes.extend(right);
+            vocab.merges.entry(bytes).or_default().push((len, rank));
+        }
+        Ok(vocab)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.decoder.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.decoder.is_empty()
+    }
+
+    pub fn decode(&self, tokens: &[TokenData]) -> Vec<TimedByte> {
+        // NOTE: a character may be split across the tokens
+        let bytes: Vec<_> = tokens
+            .iter()
+            .filter_map(|token| Some((token, self.decoder.get(&token.id)?)))
+            .flat_map(|(&TokenData { t0, t1, .. }, bytes)| {
+                bytes.iter().map(move |&byte| (byte, t0, t1))
+            })
+            .collect();
+        TimedByte::split(&bytes)
+    }
+
+    pub fn decode_text(&self, tokens: &[TokenData]) -> String {
+        let bytes: Vec<_> = self.decode(tokens).into_iter().map(|b| b.byte).collect();
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    pub fn encode(&self, bytes: &[TimedByte]) -> Result<Vec<TokenData>, EncodeError> {
+        let bytes = TimedByte::repair(bytes);
+
+        // Split into the words, each starting with a space, to bound the merges
+        let mut tokens = Vec::new();
+        let mut start = 0;
+        for end in 1..=bytes.len() {
+            if end == bytes.len() || (bytes[end].byte == b' ' && bytes[end - 1].byte != b' ') {
+                self.encode_word(&bytes[start..end], start, &mut tokens)?;
+                start = end;
+            }
+        }
+        Ok(tokens)
+    }
+
+    fn encode_word(
+        &self,
+        bytes: &[TimedByte],
+        offset: usize,
+        tokens: &mut Vec<TokenData>,
+    ) -> Result<(), EncodeError> {
+        let text: Vec<_> = bytes.iter().map(|b| b.byte).collect();
+        let rank = |start: usize, mid: usize, end: usize| {
+            let merged = &text[start..end];
+            if self.merges.is_empty() {
+                let id = *self.encoder.get(merged)?;
+                usize::try_from(id).ok()
+            } else {
+                let (_, rank) = self
+                    .merges
+                    .get(merged)?
+                    .iter()
+                    .find(|&&(len, _)| start + len == mid)?;
+                Some(*rank)
+            }
+        };
+
+        // Merge the adjacent pair with the lowest rank first
+        let mut bounds: Vec<_> = (0..=text.len()).collect();
+        while bounds.len() > 2 {
+            let best = (0..bounds.len() - 2)
+                .filter_map(|i| Some((rank(bounds[i], bounds[i + 1], bounds[i + 2])?, i)))
+                .min();
+            match best {
+                Some((_, i)) => {
+                    bounds.remove(i + 1);
+                }
+                None => break,
+            }
+        }
+
+        for window in bounds.windows(2) {
+            let (start, end) = (window[0], window[1]);
+            let id = self
+                .encoder
+                .get(&text[start..end])
+                .copied()
+                .ok_or(EncodeError {
+                    offset: offset + start,
+                    len: end - start,
+                })?;
+            let bytes = &bytes[start..end];
+            tokens.push(TokenData {
+                id,
+                t0: bytes.iter().map(|b| b.t0).min().unwrap_or_default(),
+                t1: bytes.iter().map(|b| b.t1).max().unwrap_or_default(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct EncodeError {
+    // NOTE: the position of the unknown bytes in the repaired input
+    pub offset: usize,
+    pub len: usize,
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { offset, len } = self;
+        write!(f, "{len} byte(s) at #{offset} are not in the vocabulary")
+    }
+}
+
+impl Error for EncodeError {}
+
+// NOTE: the inverse of the byte-level BPE alphabet (GPT-2 `bytes_to_unicode`)
+fn unicode_to_byte() -> HashMap<char, u8> {
+    let is_printable = |b: u8| matches!(b, b'!'..=b'~' | 0xA1..=0xAC | 0xAE..=0xFF);
+    let mut num_shifted = 0;
+    (0..=u8::MAX)
+        .map(|b| {
+            if is_printable(b) {
+                (char::from(b), b)
+            } else {
+                num_shifted += 1;
+                (char::from_u32(255 + num_shifted).unwrap_or_default(), b)
+            }
+        })
+        .collect()
+}